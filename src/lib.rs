@@ -0,0 +1,6 @@
+//! Sat-Sight: a satellite star tracking suite of tools.
+//!
+//! The library holds the star catalog handling, projections and image
+//! identification routines; `main.rs` is a driver for experimenting with them.
+
+pub mod sat_sight;
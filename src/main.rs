@@ -1,81 +1,50 @@
 use std::error::Error;
-use std::f32;
-use image::io::Reader as ImageReader;
-use sat_sight::rotate_star_vec;
+use sat_sight::sat_sight::attitude::Attitude;
+use sat_sight::sat_sight::camera::CameraModel;
+use sat_sight::sat_sight::catalog::{parse_sensor_star_file_with_schema, CatalogSchema};
+use sat_sight::sat_sight::photometry::SensorModel;
 
-use std::fs;
+use sat_sight::sat_sight::{open_star_file, project_stars, render_stars};
 
-mod sat_sight;
+// The commented out experiments below also use `image::ImageReader`, `std::fs` and the image
+// functions of `sat_sight`, import them again when bringing one back
 
-use crate::sat_sight::{open_star_file, parse_star_file, Star, get_viewable_stars, pin_prick_image, extract_lat_lon_tuples, viewable_stars, get_pix, increase_contrast, get_stars_from_image, parse_star_vec_file, xyz_to_lat_lon};
-
-const FUDGE_SIGMA: f32 = 10.0;
-const FOV: f32 = 15.0;
-const WINDOW_SIZE: u32 = 720;
+const ROLL: f64 = 0.0; // Camera roll about the boresight (deg)
+const SATURATION_MAG: f32 = 1.0;
+const LIMITING_MAG: f32 = 7.5;
 
 
 fn main() -> Result<(), Box<dyn Error>> {
     
     // ======================================== Rotate stars using quaternions and project
 
-    let csv_file = open_star_file("C:/Users/golia/Development/sat-sight/data/formated/formated_no_nova_shifted.csv")?;
-
-    // let stars = parse_star_file(csv_file)?;
+    let csv_file = open_star_file("C:/Users/golia/Development/sat-sight/data/raw/yale_bright_star_catalog_v5.csv")?;
 
-    let stars = parse_star_vec_file(csv_file)?;
+    // The render limits are sensor magnitudes, so the V magnitudes are converted with the B-V
+    // and SpType columns of the full export, the formated files have no colours
+    let stars = parse_sensor_star_file_with_schema(csv_file, &CatalogSchema::default(), &SensorModel::default())?;
     
     //let looking_direction = (-28.7,25.7);
 
     let looking_direction = (0.0, 0.0);
 
-    // create a z axis Vecoor3<f64> variable
-    let z_axis = nalgebra::Vector3::new(0.0, 0.0, 1.0);
-
-    let rotated_stars = rotate_star_vec(stars, z_axis, 10.0);
-
-    // loop through the rotated stars vector and convert the xyz cords to lat lon cords and star structs using xyz_to_lat_lon
-    let mut rotated_star_structs = Vec::new();
-    for star in rotated_stars {
-        let lat_lon = xyz_to_lat_lon(star.x, star.y, star.z);
-        let star_struct = Star {
-            hr: 0,
-            lat: lat_lon.0,
-            lon: lat_lon.1,
-            mag: 0.0,
-            fingure_print: 0.0,
-        };
-        rotated_star_structs.push(star_struct);
-    }
-
-    let viewable_stars = viewable_stars(looking_direction, rotated_star_structs.clone(), FOV);
-
-
-    println!("viewable_stars: {:#?}", viewable_stars.len());
-
-    let view_pix = get_pix(viewable_stars.clone(), FOV, WINDOW_SIZE, looking_direction);
+    // The camera looks along the looking direction with ROLL about its boresight, and the sky
+    // is turned about the z axis the way the star vectors used to be rotated before projecting
+    let sky_rotation = Attitude::from_axis_angle(nalgebra::Vector3::z(), 10.0)?;
+    let attitude = Attitude::from_ra_dec_roll(looking_direction.1, looking_direction.0, ROLL);
+    let attitude = sky_rotation.inverse() * attitude;
 
-    //println!("view_pix: {:#?}", view_pix);
+    let camera = CameraModel::default();
+    let projected_stars = project_stars(&stars, &attitude, &camera);
 
+    println!("projected_stars: {:#?}", projected_stars.len());
+    for projected in &projected_stars {
+        println!("Star: {} x: {:#?} y: {:#?}", projected.star.hr, projected.pixel.0, projected.pixel.1);
+    }
 
-
-    // create a new image and use the pixel values to draw the stars
-    let mut img = image::GrayImage::new(WINDOW_SIZE, WINDOW_SIZE);
-
-    // println!("Pixel values: {:#?}", view_pix);
-    let mut pix_out_of_bounds = 0;
-
-    for pix in view_pix {
-        if pix.0 < WINDOW_SIZE && pix.1 < WINDOW_SIZE {
-            img.put_pixel(pix.0, pix.1, image::Luma([255]));
-            println!("Pixel: x: {:#?} y: {:#?}", pix.0, pix.1)
-
-        } else {
-            println!("Pixel out of bounds: x: {:#?} y: {:#?}", pix.0, pix.1);
-            pix_out_of_bounds += 1;
-        }
-    };
-
-    println!("Total pixels out of bounds: {:#?}", pix_out_of_bounds);
+    // create a new image and draw the stars into it
+    let mut img = image::GrayImage::new(camera.width, camera.height);
+    render_stars(&mut img, &stars, &attitude, &camera, SATURATION_MAG, LIMITING_MAG)?;
     
 
 
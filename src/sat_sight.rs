@@ -4,37 +4,194 @@
 
 
 use csv::Reader;
-use std::error::Error;
 use std::fs::File;
+use std::io::{Read, Write};
 use std::path::Path;
 use image::{self, GrayImage};
-use nalgebra::{Quaternion, Vector3};
+use nalgebra::{Matrix3, Quaternion, Rotation3, UnitQuaternion, Vector3};
 
 
 use std::collections::HashMap;
-use serde::Serialize;
+use std::fmt;
+use serde::{Serialize, Serializer};
 
+pub mod astrometry;
+pub mod attitude;
+pub mod binary_catalog;
+pub mod camera;
+pub mod catalog;
+pub mod error;
+pub mod frames;
+pub mod guide_stars;
+pub mod multiples;
+pub mod photometry;
+pub mod pin_prick_table;
+pub mod precession;
+pub mod projection;
+pub mod sky_index;
+pub mod surveys;
 
-pub const IMAGE_SIZE: f32 = 648.0; // image is square
-pub const FOV: f32 = 15.0; // Field of view of the camera in degrees
+use attitude::Attitude;
+use camera::LensProjection;
+use error::SatSightError;
+use photometry::MagnitudeBand;
+use projection::{Pixel, Projection};
 
-use std::f64::consts::PI;
-use std::f32::consts::PI as PI32;
+
+
+use std::f64::consts::FRAC_PI_4;
 
 
 #[derive(Clone, Debug, Serialize)]
 pub struct Star {
-    pub hr: u32,            // Harvard Revised Number
-    pub lat: f32,           // latitude
-    pub lon: f32,           // longitude
-    pub mag: f32,           // Magnitude
+    pub hr: u32,            // Harvard Revised Number, 0 when the star is not in the BSC
+    pub id: StarId,         // Unique catalog identifier
+    pub lat: f64,           // latitude (deg)
+    pub lon: f64,           // longitude (deg)
+    pub mag: f32,           // Magnitude in `band`
+    pub band: MagnitudeBand, // Johnson V or the sensor band, see photometry
     pub fingure_print: f32, // Hashed fingerprint of the star
+    pub flags: u32,         // Catalog quality flags, see FLAG_*
+}
+
+/// Catalog identifier of a star, unique within the catalog it names
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StarId {
+    #[default]
+    Unknown,           // Not from a catalog, e.g. found in an image
+    Hr(u32),           // Harvard Revised Number (BSC)
+    Hip(u32),          // Hipparcos Catalogue number
+    Tyc(u16, u16, u8), // Tycho-2 TYC1-TYC2-TYC3
+    Gaia(u64),         // Gaia DR3 source_id
+    Hd(u32),           // Henry Draper Catalog Number
+}
+
+impl StarId {
+    /// Identifier of a BSC star, `Unknown` for the HR number 0 files use when there is none
+    pub fn from_hr(hr: u32) -> Self {
+        if hr == 0 {
+            StarId::Unknown
+        } else {
+            StarId::Hr(hr)
+        }
+    }
+}
+
+impl fmt::Display for StarId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StarId::Unknown => Ok(()),
+            StarId::Hr(hr) => write!(f, "HR {}", hr),
+            StarId::Hip(hip) => write!(f, "HIP {}", hip),
+            StarId::Tyc(tyc1, tyc2, tyc3) => write!(f, "TYC {}-{}-{}", tyc1, tyc2, tyc3),
+            StarId::Gaia(gaia) => write!(f, "Gaia DR3 {}", gaia),
+            StarId::Hd(hd) => write!(f, "HD {}", hd),
+        }
+    }
+}
+
+// Written as its display text so it fits in a single csv column
+impl Serialize for StarId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// Catalog quality flags stored in `Star::flags`
+pub const FLAG_VARIABLE: u32 = 1 << 0;           // Has a variable star identification
+pub const FLAG_MULTIPLE: u32 = 1 << 1;           // Double or multiple star
+pub const FLAG_HIGH_PROPER_MOTION: u32 = 1 << 2; // Total proper motion above HIGH_PROPER_MOTION
+pub const FLAG_MISSING_PHOTOMETRY: u32 = 1 << 3; // No B-V colour in the catalog
+
+/// Total proper motion above which a star is flagged (arcsec/yr)
+pub const HIGH_PROPER_MOTION: f64 = 0.5;
+
+/// Decides which stars take part in matching based on their catalog flags
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StarFilter {
+    pub exclude_flags: u32, // Stars with any of these flags are left out
+}
+
+impl StarFilter {
+    /// Leaves out variable stars, multiples and stars without photometry, the stars that
+    /// break magnitude based matching
+    pub fn photometric() -> Self {
+        StarFilter {
+            exclude_flags: FLAG_VARIABLE | FLAG_MULTIPLE | FLAG_MISSING_PHOTOMETRY,
+        }
+    }
+
+    pub fn allows(&self, star: &Star) -> bool {
+        star.flags & self.exclude_flags == 0
+    }
+
+    /// Returns the stars the filter allows
+    pub fn apply(&self, stars: Vec<Star>) -> Vec<Star> {
+        stars.into_iter().filter(|star| self.allows(star)).collect()
+    }
 }
 
+/// A star as a unit direction vector, with the id and magnitude of the star it came from
+#[derive(Clone, Debug, Serialize)]
 pub struct StarVec {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
+    pub hr: u32,  // Harvard Revised Number, 0 when unknown
+    pub x: f64,   // Unit vector components
+    pub y: f64,
+    pub z: f64,
+    pub mag: f32, // Magnitude in `band`
+    pub band: MagnitudeBand,
+}
+
+impl StarVec {
+    /// Builds a star vector pointing along (x, y, z), the components are normalised
+    pub fn new(hr: u32, x: f64, y: f64, z: f64, mag: f32) -> Result<Self, SatSightError> {
+        let r = (x.powi(2) + y.powi(2) + z.powi(2)).sqrt();
+        if r == 0.0 || !r.is_finite() {
+            return Err(SatSightError::degenerate("star vector has no direction"));
+        }
+        Ok(StarVec {
+            hr,
+            x: x / r,
+            y: y / r,
+            z: z / r,
+            mag,
+            band: MagnitudeBand::V,
+        })
+    }
+
+    pub fn to_vector(&self) -> Vector3<f64> {
+        Vector3::new(self.x, self.y, self.z)
+    }
+
+    /// Converts the vector back into a `Star` in the same frame
+    pub fn to_star(&self) -> Star {
+        // The vector is normalised on construction so it always has a direction
+        let (lat, lon) = xyz_to_lat_lon(self.x, self.y, self.z).unwrap_or((0.0, 0.0));
+        Star {
+            hr: self.hr,
+            id: StarId::from_hr(self.hr),
+            lat,
+            lon,
+            mag: self.mag,
+            band: self.band,
+            fingure_print: 0.0,
+            flags: 0,
+        }
+    }
+}
+
+impl From<&Star> for StarVec {
+    fn from(star: &Star) -> Self {
+        let v = frames::lat_lon_to_unit_vector(star.lat, star.lon);
+        StarVec {
+            hr: star.hr,
+            x: v.x,
+            y: v.y,
+            z: v.z,
+            mag: star.mag,
+            band: star.band,
+        }
+    }
 }
 
 // ===== Quaternion Math ==========================================================================
@@ -53,6 +210,12 @@ pub fn axis_angle_to_quaternion(axis: Vector3<f64>, theta: f64) -> Quaternion<f6
     )
 }
 
+/// Converts a rotation matrix into the quaternion that applies the same rotation with `rotate_vectors`
+pub fn rotation_matrix_to_quaternion(m: &Matrix3<f64>) -> Quaternion<f64> {
+    let rotation = Rotation3::from_matrix_unchecked(*m);
+    UnitQuaternion::from_rotation_matrix(&rotation).into_inner()
+}
+
 fn rotate_vector(q: &Quaternion<f64>, v: &Vector3<f64>) -> Vector3<f64> {
     let qv = Quaternion::new(0.0, v.x, v.y, v.z);
     let q_conj = q.conjugate();
@@ -69,12 +232,12 @@ pub fn rotate_vectors(axis: Vector3<f64>, theta: f64, vectors: Vec<Vector3<f64>>
 pub fn rotate_star_vec(star_vec: Vec<StarVec>, axis: Vector3<f64>, theta: f64) -> Vec<StarVec> {
     let q = axis_angle_to_quaternion(axis, theta);
     star_vec.into_iter().map(|v| {
-        let v = Vector3::new(v.x as f64, v.y as f64, v.z as f64);
-        let rotated_v = rotate_vector(&q, &v);
+        let rotated_v = rotate_vector(&q, &v.to_vector()).normalize();
         StarVec {
-            x: rotated_v.x as f32,
-            y: rotated_v.y as f32,
-            z: rotated_v.z as f32,
+            x: rotated_v.x,
+            y: rotated_v.y,
+            z: rotated_v.z,
+            ..v
         }
     }).collect()
 }
@@ -82,71 +245,98 @@ pub fn rotate_star_vec(star_vec: Vec<StarVec>, axis: Vector3<f64>, theta: f64) -
 
 
 
-// ==== Gnomonic Projection ========================================================================
-// Functions that perform the gnomonic projection
+// ==== Azimuthal Projections ======================================================================
+// Gnomonic, orthographic, stereographic and equidistant projections of the sphere onto the plane
+// tangent at the view direction, and their inverses. Angles are in radians and plane coordinates
+// are in units of the sphere's radius. x grows towards increasing lambda (east) and y towards
+// increasing phi (north), the camera frame +x and +y at zero roll. Scaled by the focal length in
+// pixels and moved to the principal point they are the pixel of a `CameraModel` with the same
+// lens and no distortion, with east to the right and north down the rows.
 // =================================================================================================
 
-fn project_astronomical_coords(
-    alpha: f64, delta: f64,
-    alpha0: f64, delta0: f64,
-    scale: f64
-) -> (u32, u32) {
-    // Convert all angles from degrees to radians for trigonometric functions
-    // let alpha = alpha.to_radians();
-    // let delta = delta.to_radians();
-    // let alpha0 = alpha0.to_radians();
-    // let delta0 = delta0.to_radians();
-    // //let scale = scale.to_radians();
-
-    // let A = delta.cos() * (alpha - alpha0).cos();
-    // let F = scale  * (180.0 / PI) / (delta0.sin() * delta.sin() + A * delta0.cos());
-
-    // let mut LINE = F * (delta0.cos() * delta.sin() - A * delta0.sin());
-    // let mut SAMPLE = F * delta.cos() * (alpha - alpha0).sin();
-
-    // LINE += 360.0;
-    // SAMPLE += 360.0;
-    
+/// Projects a point with `projection`, which sets the distance from the view direction
+fn azimuthal_projection(projection: LensProjection, lambda_view: f64, phi_view: f64, lambda: f64, phi: f64) -> Result<(f64, f64), SatSightError> {
 
-    // (LINE as u32, SAMPLE as u32)
+    let cos_c = phi.sin() * phi_view.sin() + phi.cos() * phi_view.cos() * (lambda - lambda_view).cos();
 
-    let lambda_a = alpha.to_radians();
-    let phi_a = delta.to_radians();
-    let lambda_b = alpha0.to_radians();
-    let phi_b = delta0.to_radians();
+    // Direction away from the view direction, scaled by sin(c)
+    let x = phi.cos() * (lambda - lambda_view).sin();
+    let y = phi_view.cos() * phi.sin() - phi_view.sin() * phi.cos() * (lambda - lambda_view).cos();
 
-    // let phi_a = alpha.to_radians();
-    // let lambda_a = delta.to_radians();
-    // let phi_b = alpha0.to_radians();
-    // let lambda_b = delta0.to_radians();
-    
+    let sin_c = x.hypot(y);
+    let radius = projection.radius(sin_c.atan2(cos_c))
+        .ok_or_else(|| SatSightError::degenerate("point is too far from the view direction for the projection"))?;
+    if sin_c == 0.0 {
+        return Ok((0.0, 0.0));
+    }
 
-    //let scale_scale = 100.0 * scale;
-    
-    let cos_c = (phi_a.sin() * phi_b.sin())
-    + (phi_a.cos() * phi_b.cos() * (lambda_b - lambda_a).cos());
+    Ok((x * radius / sin_c, y * radius / sin_c))
+}
 
-    let mut x = scale * (phi_b.cos() * (lambda_b - lambda_a).sin()) / cos_c;
-    let mut y = scale * ((phi_a.cos() * phi_b.sin() - phi_a.sin() * phi_b.cos() * (lambda_b - lambda_a).cos()) 
-        / cos_c);
+/// Returns the `(lambda, phi)` of a point on the plane of `projection`
+fn inverse_azimuthal_projection(projection: LensProjection, lambda_view: f64, phi_view: f64, x: f64, y: f64) -> Result<(f64, f64), SatSightError> {
 
+    let radius = x.hypot(y);
+    if !radius.is_finite() {
+        return Err(SatSightError::degenerate("point on the plane is not finite"));
+    }
+    if radius == 0.0 {
+        return Ok((lambda_view, phi_view));
+    }
+    let c = projection.angle(radius);
+    if projection.radius(c).is_none_or(|rim| radius > rim + f64::EPSILON * rim.max(1.0)) {
+        return Err(SatSightError::degenerate("point is outside the projected sphere"));
+    }
 
-    x += 360.0;
-    y += 360.0;
+    let (sin_c, cos_c) = c.sin_cos();
+    let phi = (cos_c * phi_view.sin() + y * sin_c * phi_view.cos() / radius).clamp(-1.0, 1.0).asin();
+    let lambda = lambda_view + (x * sin_c).atan2(radius * phi_view.cos() * cos_c - y * phi_view.sin() * sin_c);
 
-    (x as u32, y as u32)
+    Ok((lambda, phi))
 }
 
+/// Projects a point onto the plane tangent at the view direction.
+/// Points 90 degrees or more away from the view direction have no projection.
+pub fn gnomonic_porjection(lambda_view: f64, phi_view: f64, lambda: f64, phi: f64) -> Result<(f64, f64), SatSightError> {
+    azimuthal_projection(LensProjection::Gnomonic, lambda_view, phi_view, lambda, phi)
+}
 
-pub fn gnomonic_porjection(lambda_view: f64, phi_view: f64, lambda: f64, phi: f64) -> (f64, f64) {
+/// Returns the `(lambda, phi)` of a point on the gnomonic plane
+pub fn inverse_gnomonic_projection(lambda_view: f64, phi_view: f64, x: f64, y: f64) -> Result<(f64, f64), SatSightError> {
+    inverse_azimuthal_projection(LensProjection::Gnomonic, lambda_view, phi_view, x, y)
+}
 
-    let cos_c = phi.sin() * phi_view.sin() + phi.cos() * phi_view.cos() * (lambda_view - lambda).cos();
+/// Projects a point straight down onto the plane, the view seen from infinitely far away.
+/// Points more than 90 degrees away from the view direction have no projection.
+pub fn orthographic_projection(lambda_view: f64, phi_view: f64, lambda: f64, phi: f64) -> Result<(f64, f64), SatSightError> {
+    azimuthal_projection(LensProjection::Orthographic, lambda_view, phi_view, lambda, phi)
+}
 
-    let x = phi_view.cos() * (lambda_view - lambda).sin() / cos_c;
-    
-    let y = (phi.cos() * phi_view.sin() - phi.sin() * phi_view.cos() * (lambda_view - lambda).cos()) / cos_c;
+/// Returns the `(lambda, phi)` of a point on the orthographic plane, which has to be in the unit circle
+pub fn inverse_orthographic_projection(lambda_view: f64, phi_view: f64, x: f64, y: f64) -> Result<(f64, f64), SatSightError> {
+    inverse_azimuthal_projection(LensProjection::Orthographic, lambda_view, phi_view, x, y)
+}
 
-    (x, y)
+/// Projects a point from the opposite side of the sphere, which keeps angles and small circles.
+/// Only the point opposite the view direction has no projection.
+pub fn stereographic_projection(lambda_view: f64, phi_view: f64, lambda: f64, phi: f64) -> Result<(f64, f64), SatSightError> {
+    azimuthal_projection(LensProjection::Stereographic, lambda_view, phi_view, lambda, phi)
+}
+
+/// Returns the `(lambda, phi)` of a point on the stereographic plane
+pub fn inverse_stereographic_projection(lambda_view: f64, phi_view: f64, x: f64, y: f64) -> Result<(f64, f64), SatSightError> {
+    inverse_azimuthal_projection(LensProjection::Stereographic, lambda_view, phi_view, x, y)
+}
+
+/// Projects a point so its distance from the origin is its angle from the view direction, the
+/// projection of a fisheye lens. Only the point opposite the view direction has no projection.
+pub fn equidistant_projection(lambda_view: f64, phi_view: f64, lambda: f64, phi: f64) -> Result<(f64, f64), SatSightError> {
+    azimuthal_projection(LensProjection::Equidistant, lambda_view, phi_view, lambda, phi)
+}
+
+/// Returns the `(lambda, phi)` of a point on the equidistant plane, which has to be within pi of the origin
+pub fn inverse_equidistant_projection(lambda_view: f64, phi_view: f64, x: f64, y: f64) -> Result<(f64, f64), SatSightError> {
+    inverse_azimuthal_projection(LensProjection::Equidistant, lambda_view, phi_view, x, y)
 }
 
 // === Viewable Stars ==============================================================================
@@ -155,11 +345,11 @@ pub fn gnomonic_porjection(lambda_view: f64, phi_view: f64, lambda: f64, phi: f6
 
 
 /// Returns the viewable stars from a list of stars based on the field of view and looking direction
-/// pub fn get_viewable_stars(fov: f32, window_size: u32, looking_direction: (f32, f32), stars: Vec<Star>) -> Vec<(u32, u32)> {}
+/// pub fn get_viewable_stars(fov: f64, window_size: u32, looking_direction: (f64, f64), stars: Vec<Star>) -> Vec<(u32, u32)> {}
 /// DEPRECEATED
-pub fn get_viewable_stars(fov: f32, window_size: u32, looking_direction: (f32, f32), stars: Vec<Star>) -> Vec<(u32, u32)> {
+pub fn get_viewable_stars(fov: f64, window_size: u32, looking_direction: (f64, f64), stars: Vec<Star>) -> Vec<(u32, u32)> {
     let half_fov = fov.to_radians() / 2.0;
-    let window_center = (window_size as f32 / 2.0, window_size as f32 / 2.0);
+    let window_center = (window_size as f64 / 2.0, window_size as f64 / 2.0);
 
     let looking_direction_rad = (looking_direction.0.to_radians(), looking_direction.1.to_radians());
 
@@ -169,7 +359,7 @@ pub fn get_viewable_stars(fov: f32, window_size: u32, looking_direction: (f32, f
             let angle_diff = angle_between_directions(looking_direction_rad, star_direction);
 
             if angle_diff <= half_fov {
-                let (dx, dy) = angle_to_pixel_offset(angle_diff, fov, window_size as f32);
+                let (dx, dy) = angle_to_pixel_offset(angle_diff, fov, window_size as f64);
                 let (x, y) = (
                     (window_center.0 + dx) as u32,
                     (window_center.1 + dy) as u32,
@@ -183,23 +373,24 @@ pub fn get_viewable_stars(fov: f32, window_size: u32, looking_direction: (f32, f
         .collect()
 }
 
-pub fn angle_between(lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> f32 {
-    let phi1 = lat1.to_radians();
-    let phi2: f32 = lat2.to_radians();
-    let lambda1 = lon1.to_radians();
-    let lambda2 = lon2.to_radians();
-
-    let delat_lambda = (lambda2 - lambda1).abs();
-
-    (phi1.sin() * phi2.sin() + phi1.cos() * phi2.cos() * delat_lambda.cos()).acos()
+/// Angle (rad) between two directions given as latitude and longitude in degrees
+pub fn angle_between(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    angular_separation(lat1.to_radians(), lon1.to_radians(), lat2.to_radians(), lon2.to_radians())
 }
 
+/// Returns the stars within a cone of `fov` degrees around the looking direction.
+/// The cone does not match a rectangular image, see `frustum_stars` for exact culling.
+pub fn viewable_stars(looking_direction: (f64, f64), stars: Vec<Star>, fov: f64) -> Vec<Star> {
+    viewable_stars_filtered(looking_direction, stars, fov, &StarFilter::default())
+}
 
-pub fn viewable_stars(looking_direction: (f32, f32), stars: Vec<Star>, fov: f32) -> Vec<Star> {
+/// Same as `viewable_stars` but leaves out the stars the filter does not allow
+pub fn viewable_stars_filtered(looking_direction: (f64, f64), stars: Vec<Star>, fov: f64, filter: &StarFilter) -> Vec<Star> {
     let half_fov = fov.to_radians() / 2.0;
     //println!("half_fov: {}", half_fov);
 
     stars.into_iter()
+        .filter(|star| filter.allows(star))
         .filter_map(|star| {
             let angle_diff = angle_between(looking_direction.0, looking_direction.1, star.lat, star.lon);
             if angle_diff <= half_fov {
@@ -215,47 +406,68 @@ pub fn viewable_stars(looking_direction: (f32, f32), stars: Vec<Star>, fov: f32)
         
 }
 
+/// Returns the stars inside the field of view of a camera with `attitude`, roll included.
+///
+/// Stars are culled against the actual image through the optics, so stars in the corners of
+/// the image are kept and none are returned that land outside it. `margin` (pixels) widens the
+/// image on every side, e.g. to keep stars whose blur reaches onto the edge.
+pub fn frustum_stars(stars: Vec<Star>, attitude: &Attitude, projection: &dyn Projection, margin: f64) -> Vec<Star> {
+    frustum_stars_filtered(stars, attitude, projection, margin, &StarFilter::default())
+}
 
-pub fn get_pix(stars: Vec<Star>, fov: f32, screen_size: u32, looking_direction: (f32, f32)) -> Vec<(u32, u32)> {
-        
-    //let scale = screen_size as f32 / fov;
-    
-    let scale = 2500;
-
+/// Same as `frustum_stars` but leaves out the stars the filter does not allow
+pub fn frustum_stars_filtered(stars: Vec<Star>, attitude: &Attitude, projection: &dyn Projection, margin: f64, filter: &StarFilter) -> Vec<Star> {
+    let field_radius = projection.field_radius(margin);
     stars.into_iter()
-    .map(|star| 
-        //convert_between_angle_and_pixel(fov, screen_size, looking_direction.0, looking_direction.1, star.lat, star.lon)
-        
-        project_astronomical_coords(
-            looking_direction.0 as f64, looking_direction.1 as f64,
-            star.lat as f64, star.lon as f64,
-            scale as f64
-        )
+        .filter(|star| filter.allows(star))
+        .filter(|star| {
+            let direction = attitude.inverse_rotate(&frames::lat_lon_to_unit_vector(star.lat, star.lon));
+            in_frustum(projection, &direction, field_radius, margin)
+        })
+        .collect()
+}
+
+/// True when a camera frame direction lands on the image widened by `margin` pixels.
+/// Directions further off the boresight than `field_radius` (deg, see
+/// `Projection::field_radius`) are rejected before projecting, so no lens can fold them back
+/// onto the image.
+pub(crate) fn in_frustum(projection: &dyn Projection, direction: &Vector3<f64>, field_radius: f64, margin: f64) -> bool {
+    let off_axis = direction.xy().norm().atan2(direction.z).to_degrees();
+    off_axis <= field_radius
+        && projection
+            .project(direction)
+            .is_some_and(|pixel| projection.contains_with_margin(pixel, margin))
+}
+
+/// Returns the sub-pixel positions of the stars seen through `projection` looking at
+/// `looking_direction` (lat, lon) without roll. Stars that do not land on the image are left out.
+pub fn get_pix(stars: Vec<Star>, projection: &dyn Projection, looking_direction: (f64, f64)) -> Vec<Pixel> {
+    let attitude = Attitude::from_ra_dec_roll(looking_direction.1, looking_direction.0, 0.0);
 
-    ).collect()
+    project_stars(&stars, &attitude, projection)
+        .into_iter()
+        .map(|projected| projected.pixel)
+        .collect()
 }
 
-pub fn convert_between_angle_and_pixel(fov: f32, screen_size: u32, lat1: f32, lon1: f32, lat2: f32, lon2: f32) -> (u32, u32) {
-    let screen_center = screen_size as f32 / 2.0;
+pub fn convert_between_angle_and_pixel(fov: f64, screen_size: u32, lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> (u32, u32) {
+    let screen_center = screen_size as f64 / 2.0;
 
-    let pix_ang = screen_size as f32 / fov;
-    // let ang_pix = fov / screen_size as f32;
+    let pix_ang = screen_size as f64 / fov;
+    // let ang_pix = fov / screen_size as f64;
     //println!("pix_ang: {}, ang_pix: {}", pix_ang, ang_pix);
 
     //let lat_delta = 180.0 - ((lat1 - lat2).abs() - 180.0).abs();
     //let lon_delta = 180.0 - ((lon1 - lon2).abs() - 180.0).abs();
 
-    let mut lat_delta = 0.0;
-    let mut lon_delta = 0.0;
-
     // let mut lat_delta = lat1 - lat2;
     // let mut lon_delta = lon1 - lon2;  
     
     // // lat_delta = (lat_delta + 180.0) % 360.0 - 180.0; // needs some work, dosn't work for negative long
     // // lon_delta = (lon_delta + 180.0) % 360.0 - 180.0;
 
-    lat_delta = 180.0 - ((lat1 - lat2).abs() - 180.0).abs();
-    lon_delta = 180.0 - ((lon1 - lon2).abs() - 180.0).abs();
+    let lat_delta = 180.0 - ((lat1 - lat2).abs() - 180.0).abs();
+    let lon_delta = 180.0 - ((lon1 - lon2).abs() - 180.0).abs();
     
 
     // lat_delta = angle_between(lat1,0.0, lat2, 0.0);
@@ -271,8 +483,8 @@ pub fn convert_between_angle_and_pixel(fov: f32, screen_size: u32, lat1: f32, lo
     //println!("lat_delta: {}, lon_delta: {}", lat_delta, lon_delta);
     //println!("px_y: {}, px_x: {}", px_y, px_x);
 
-    let px_y_shfited = px_y as f32  + screen_center;
-    let px_x_shifted = px_x as f32 + screen_center;
+    let px_y_shfited = px_y + screen_center;
+    let px_x_shifted = px_x + screen_center;
 
     //println!("px_y_shifted: {}, px_x_shifted: {}", px_y_shfited, px_x_shifted);
     //println!("px_y_shifted u32: {}, px_x_shifted u32: {}", px_y_shfited as u32, px_x_shifted as u32);
@@ -281,80 +493,165 @@ pub fn convert_between_angle_and_pixel(fov: f32, screen_size: u32, lat1: f32, lo
 }
 
 
-pub fn angle_between_directions(dir1: (f32, f32), dir2: (f32, f32)) -> f32 {
-    let (lat1, lon1) = dir1;
-    let (lat2, lon2) = dir2;
-
-    let cos_lat1 = lat1.cos();
-    let cos_lat2 = lat2.cos();
-
-    let part1 = (lon2 - lon1).cos() * cos_lat2;
-    let part2 = lat2.sin() * cos_lat1 - lat1.sin() * cos_lat2 * (lon2 - lon1).cos();
-    part1.atan2(part2).abs()
+/// Angle (rad) between two (lat, lon) directions given in radians, see `angular_separation`
+pub fn angle_between_directions(dir1: (f64, f64), dir2: (f64, f64)) -> f64 {
+    angular_separation(dir1.0, dir1.1, dir2.0, dir2.1)
 }
 
-pub fn angle_to_pixel_offset(angle: f32, fov: f32, window_size: f32) -> (f32, f32) {
+pub fn angle_to_pixel_offset(angle: f64, fov: f64, window_size: f64) -> (f64, f64) {
     let ratio = angle / fov;
     let dx = ratio * window_size / 2.0;
-    let dy = dx * (PI32 / 4.0).tan();
+    let dy = dx * FRAC_PI_4.tan();
     (dx, dy)
 }
 
-// ==== Utility Functions ==========================================================================
-// Functions that perform general utility operations
+// ==== Camera Frame Projection ====================================================================
+// Projection of stars through a full three axis attitude, including roll about the boresight
 // =================================================================================================
 
-/// Calculates the distance between two stars in kilometers
-pub fn calculate_distance_between_stars(star1: &Star, star2: &Star) -> f32 {
-    let earth_radius = 6371.0; // Radius of the Earth in kilometers
+/// A star and where it falls on the image
+#[derive(Clone, Debug)]
+pub struct ProjectedStar {
+    pub star: Star,
+    pub pixel: Pixel, // Sub-pixel (column, row) from the top left corner, always on the image
+}
 
-    // Convert the latitude and longitude of the stars to radians
-    let lat1_rad = star1.lat.to_radians();
-    let lon1_rad = star1.lon.to_radians();
-    let lat2_rad = star2.lat.to_radians();
-    let lon2_rad = star2.lon.to_radians();
+/// Projects stars into the image of a camera with the given attitude.
+///
+/// The attitude is relative to the frame of the star positions (galactic for the catalogs).
+/// Each star is rotated into the camera frame, +z along the boresight, and projected through
+/// the optics. Camera +x runs along the columns and +y along the rows, so at zero roll east is
+/// right and north is down. Only the stars that land on the image are returned.
+pub fn project_stars(stars: &[Star], attitude: &Attitude, projection: &dyn Projection) -> Vec<ProjectedStar> {
+    stars
+        .iter()
+        .filter_map(|star| {
+            let direction = frames::lat_lon_to_unit_vector(star.lat, star.lon);
+            let pixel = projection.project(&attitude.inverse_rotate(&direction))?;
+            if !projection.contains(pixel) {
+                return None;
+            }
+            Some(ProjectedStar { star: star.clone(), pixel })
+        })
+        .collect()
+}
 
-    // Calculate the distance between the two stars
-    let delta_lat = lat2_rad - lat1_rad; 
-    let delta_lon = lon2_rad - lon1_rad;
+/// The up to four pixels around a sub-pixel position with their bilinear weights.
+/// Pixel centres are at half pixels, and pixels off the image are left out.
+fn bilinear_neighbours(image: &GrayImage, pixel: Pixel) -> impl Iterator<Item = ((u32, u32), f64)> {
+    let (u, v) = (pixel.0 - 0.5, pixel.1 - 0.5);
+    let (column, row) = (u.floor(), v.floor());
+    let (fraction_u, fraction_v) = (u - column, v - row);
+    let (width, height) = (image.width() as f64, image.height() as f64);
+
+    [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].into_iter().filter_map(move |(du, dv)| {
+        let (x, y) = (column + du, row + dv);
+        if !(0.0..width).contains(&x) || !(0.0..height).contains(&y) {
+            return None;
+        }
+        let weight = (if du == 0.0 { 1.0 - fraction_u } else { fraction_u })
+            * (if dv == 0.0 { 1.0 - fraction_v } else { fraction_v });
+        Some(((x as u32, y as u32), weight))
+    })
+}
 
-    // Haversine formula
-    let a = (delta_lat / 2.0).sin().powi(2) 
-        + lat1_rad.cos() * lat2_rad.cos() * (delta_lon / 2.0).sin().powi(2);
-    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+/// Image value at a sub-pixel position, bilinearly interpolated between the pixel centres.
+/// `None` when the position is off the image, the edge pixels fade out over their outer half.
+pub fn sample_image(image: &GrayImage, pixel: Pixel) -> Option<f64> {
+    if !(0.0..image.width() as f64).contains(&pixel.0) || !(0.0..image.height() as f64).contains(&pixel.1) {
+        return None;
+    }
+    Some(
+        bilinear_neighbours(image, pixel)
+            .map(|((x, y), weight)| image.get_pixel(x, y)[0] as f64 * weight)
+            .sum(),
+    )
+}
 
-    let distance = earth_radius * c;
+/// Draws the stars seen with `attitude` through `projection` into `image` with a brightness
+/// from their magnitude, see `photometry::magnitude_to_intensity`. Each star is anti-aliased
+/// over the four pixels around its sub-pixel position and overlapping stars add up.
+///
+/// The magnitude limits are in the sensor's band, so every star has to be a sensor band star
+/// (`parse_sensor_star_file_with_schema`, `BscStar::to_sensor_star`), a V band star is an error.
+pub fn render_stars(image: &mut GrayImage, stars: &[Star], attitude: &Attitude, projection: &dyn Projection, saturation_mag: f32, limiting_mag: f32) -> Result<(), SatSightError> {
+    if let Some(star) = stars.iter().find(|star| star.band != MagnitudeBand::Sensor) {
+        return Err(SatSightError::WrongMagnitudeBand(format!("{} has a {:?} magnitude, rendering needs sensor magnitudes", star.id, star.band)));
+    }
 
-    distance
+    let mut flux = vec![0.0_f64; image.width() as usize * image.height() as usize];
+    for projected in project_stars(stars, attitude, projection) {
+        let intensity = photometry::magnitude_to_intensity(projected.star.mag, saturation_mag, limiting_mag) as f64;
+        for ((x, y), weight) in bilinear_neighbours(image, projected.pixel) {
+            flux[y as usize * image.width() as usize + x as usize] += intensity * weight;
+        }
+    }
+    for (pixel, flux) in image.iter_mut().zip(flux) {
+        *pixel = (*pixel as f64 + flux).round().min(255.0) as u8;
+    }
+    Ok(())
 }
 
-/// convert a x,y,z vector to a latitude and longitude
-pub fn xyz_to_lat_lon(x: f32, y: f32, z: f32) -> (f32, f32) {
-    let r = (x.powi(2) + y.powi(2) + z.powi(2)).sqrt();
-    let lat = (z / r).asin().to_degrees();
-    let lon = y.atan2(x).to_degrees();
-    (lat, lon)
+/// Unit vector, in the frame of the attitude, of a star centroid found at `pixel`
+pub fn centroid_to_vector(pixel: (f64, f64), attitude: &Attitude, projection: &dyn Projection) -> Vector3<f64> {
+    attitude.rotate(&projection.unproject(pixel))
 }
 
+// ==== Utility Functions ==========================================================================
+// Functions that perform general utility operations
+// =================================================================================================
 
-/// Calculates the baring between two stars in degrees
-pub fn calculate_baring_between_stars(star1: &Star, star2: &Star) -> f32 {
-    // Convert the latitude and longitude of the stars to radians
-    let lat1_rad = star1.lat.to_radians();
-    let lon1_rad = star1.lon.to_radians();
-    let lat2_rad = star2.lat.to_radians();
-    let lon2_rad = star2.lon.to_radians();
+/// Angle (rad) between the directions (`phi1`, `lambda1`) and (`phi2`, `lambda2`), latitudes
+/// and longitudes in radians.
+///
+/// Uses the Vincenty formula, an atan2 of the sine and cosine of the angle, which stays
+/// accurate for every separation. The acos of a dot product loses most of its digits below an
+/// arcminute and the haversine loses them near 180 degrees.
+pub fn angular_separation(phi1: f64, lambda1: f64, phi2: f64, lambda2: f64) -> f64 {
+    let (sin_phi1, cos_phi1) = phi1.sin_cos();
+    let (sin_phi2, cos_phi2) = phi2.sin_cos();
+    let (sin_delta, cos_delta) = (lambda2 - lambda1).sin_cos();
+
+    let sine = (cos_phi2 * sin_delta).hypot(cos_phi1 * sin_phi2 - sin_phi1 * cos_phi2 * cos_delta);
+    let cosine = sin_phi1 * sin_phi2 + cos_phi1 * cos_phi2 * cos_delta;
+    sine.atan2(cosine)
+}
 
-    // Calculate the baring between the two stars
-    let delta_lon = lon2_rad - lon1_rad;
+/// Position angle (rad) of (`phi2`, `lambda2`) seen from (`phi1`, `lambda1`), measured from the
+/// direction of increasing latitude towards increasing longitude, in [0, 2 pi).
+/// Zero when the points coincide or the first one is at a pole.
+pub fn position_angle(phi1: f64, lambda1: f64, phi2: f64, lambda2: f64) -> f64 {
+    let (sin_phi1, cos_phi1) = phi1.sin_cos();
+    let (sin_phi2, cos_phi2) = phi2.sin_cos();
+    let (sin_delta, cos_delta) = (lambda2 - lambda1).sin_cos();
+
+    let east = cos_phi2 * sin_delta;
+    let north = cos_phi1 * sin_phi2 - sin_phi1 * cos_phi2 * cos_delta;
+    east.atan2(north).rem_euclid(std::f64::consts::TAU)
+}
 
-    // Haversine formula
-    let y = delta_lon.sin() * lat2_rad.cos();
-    let x = lat1_rad.cos() * lat2_rad.sin() - lat1_rad.sin() * lat2_rad.cos() * delta_lon.cos();
+/// Latitude and longitude of a star in radians
+fn star_radians(star: &Star) -> (f64, f64) {
+    (star.lat.to_radians(), star.lon.to_radians())
+}
 
-    let baring = y.atan2(x).to_degrees();
+/// Calculates the angular distance between two stars in radians, see `angular_separation`
+pub fn calculate_distance_between_stars(star1: &Star, star2: &Star) -> f64 {
+    let ((phi1, lambda1), (phi2, lambda2)) = (star_radians(star1), star_radians(star2));
+    angular_separation(phi1, lambda1, phi2, lambda2)
+}
 
-    baring
+/// convert a x,y,z vector to a latitude and longitude
+pub fn xyz_to_lat_lon(x: f64, y: f64, z: f64) -> Result<(f64, f64), SatSightError> {
+    frames::unit_vector_to_lat_lon(&Vector3::new(x, y, z))
+}
+
+
+/// Calculates the baring from the first star to the second in degrees, in (-180, 180]
+pub fn calculate_baring_between_stars(star1: &Star, star2: &Star) -> f64 {
+    let ((phi1, lambda1), (phi2, lambda2)) = (star_radians(star1), star_radians(star2));
+    let baring = position_angle(phi1, lambda1, phi2, lambda2).to_degrees();
+    if baring > 180.0 { baring - 360.0 } else { baring }
 }
 
 /// Extracts the latitude and longitude tuples from a vector of stars
@@ -366,9 +663,20 @@ pub fn extract_lat_lon_tuples(stars: &[Star]) -> Vec<(u32, u32)> {
 }
 
 
+/// Sums the image values where the stars would land if the camera had `attitude`, the
+/// goodness of the attitude for the blur and prick method. The image is interpolated at the
+/// sub-pixel positions, so the score changes smoothly with the attitude. Stars that land off
+/// the image add nothing.
+pub fn pin_prick_image(image: &GrayImage, stars: &[Star], attitude: &Attitude, projection: &dyn Projection) -> f64 {
+    project_stars(stars, attitude, projection)
+        .iter()
+        .filter_map(|projected| sample_image(image, projected.pixel))
+        .sum()
+}
+
 /// This function takes an image and tests all locations of possibly star locations
 /// and returns a sum of the values that it tests
-pub fn pin_prick_image(image: &GrayImage, coordinates: &Vec<(u32, u32)>) -> u32 {
+pub fn pin_prick_pixels(image: &GrayImage, coordinates: &[(u32, u32)]) -> u32 {
     coordinates
         .iter()
         .map(|(x, y)| {
@@ -386,16 +694,28 @@ pub fn pin_prick_image(image: &GrayImage, coordinates: &Vec<(u32, u32)>) -> u32
 
 
 
-pub fn calculate_star_fingure_prints(stars: Vec<Star>) -> Vec<Star> {
+/// Same as `calculate_star_fingure_prints` for only the stars the filter allows
+pub fn calculate_star_fingure_prints_filtered(stars: Vec<Star>, filter: &StarFilter) -> Result<Vec<Star>, SatSightError> {
+    calculate_star_fingure_prints(filter.apply(stars))
+}
+
+/// Calculates a fingerprint for every star from its 5 closest neighbours: the sum of their
+/// angular distances plus the sum of the gaps between their sorted barings, all in degrees.
+/// Needs at least two stars so every star has a neighbour.
+pub fn calculate_star_fingure_prints(stars: Vec<Star>) -> Result<Vec<Star>, SatSightError> {
+    if stars.len() < 2 {
+        return Err(SatSightError::degenerate("fingerprints need at least two stars"));
+    }
     // let mut star_fingure_prints = HashMap::new();
     let mut stars_with_fingure_prints: Vec<Star> = Vec::new();
     for i in 0..stars.len() {
-        let mut shortest_constellations = HashMap::new();
+        // Neighbours are keyed by their index in `stars`, unique even among stars without a catalog id
+        let mut shortest_constellations: HashMap<usize, f64> = HashMap::new();
         for j in 0..stars.len() {
             if i != j {
                 let distance = calculate_distance_between_stars(&stars[i], &stars[j]);
                 if shortest_constellations.len() < 5 {
-                    shortest_constellations.insert(stars[j].hr, distance);
+                    shortest_constellations.insert(j, distance);
                 } else {
                     let mut max_distance = 0.0;
                     let mut max_distance_star = 0;
@@ -407,7 +727,7 @@ pub fn calculate_star_fingure_prints(stars: Vec<Star>) -> Vec<Star> {
                     }
                     if distance < max_distance {
                         shortest_constellations.remove(&max_distance_star);
-                        shortest_constellations.insert(stars[j].hr, distance);
+                        shortest_constellations.insert(j, distance);
                     }
                 }
             }
@@ -416,35 +736,30 @@ pub fn calculate_star_fingure_prints(stars: Vec<Star>) -> Vec<Star> {
 
         let mut baring_collection = Vec::new();
         let mut baring_sum = 0.0;
-        for (star, _) in &shortest_constellations {
-            let baring = calculate_baring_between_stars(&stars[i], &stars[stars.iter().position(|x| x.hr == *star).unwrap()]);
+        for &neighbour in shortest_constellations.keys() {
+            let baring = calculate_baring_between_stars(&stars[i], &stars[neighbour]);
             baring_collection.push(baring+180.0);
-            baring_collection.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            // calculate the difference between the baring of the stars
-            //print!("baring length: {}, ", baring_collection.len());  
         }
-        for i in 0..(baring_collection.len() - 1) {
-            let difference = baring_collection[i+1] - baring_collection[i];
+        baring_collection.sort_by(|a, b| a.total_cmp(b));
+        // calculate the difference between the baring of the stars
+        for pair in baring_collection.windows(2) {
+            let difference = pair[1] - pair[0];
             //println!("difference: {}", difference);
             baring_sum += difference;
         }
         
-        let distance_sum: f32 = shortest_constellations.values().sum();
-        println!("Star: {}, print: {}", stars[i].hr, distance_sum + baring_sum);
-        // println!("star: {}, shortest_constellations: {:?}", stars[i].hr, shortest_constellations);
+        // Distances are in radians and barings in degrees, sum both in degrees
+        let distance_sum = shortest_constellations.values().sum::<f64>().to_degrees();
         stars_with_fingure_prints.push(Star {
-            hr: stars[i].hr,
-            lat: stars[i].lat,
-            lon: stars[i].lon,
-            mag: stars[i].mag,
-            fingure_print: baring_sum + distance_sum,
+            fingure_print: (baring_sum + distance_sum) as f32,
+            ..stars[i].clone()
         });
 
         // let modulo_sum = sum % 1.0; // Assuming you want to take the modulo with 1.0
         // star_fingure_prints.insert(modulo_sum, stars[i].hr);
     }
     // star_fingure_prints
-    stars_with_fingure_prints
+    Ok(stars_with_fingure_prints)
 }
 
 
@@ -453,10 +768,10 @@ pub fn cartesian_to_corrdinates(
     y: u32,
     image_width: u32,
     image_height: u32,
-    viewport_deg: f32,
-) -> (f32, f32) {
-    let lat = (y as f32 / image_height as f32) * viewport_deg;
-    let lon = (x as f32 / image_width as f32) * viewport_deg;
+    viewport_deg: f64,
+) -> (f64, f64) {
+    let lat = (y as f64 / image_height as f64) * viewport_deg;
+    let lon = (x as f64 / image_width as f64) * viewport_deg;
     (lat, lon)
 }
 
@@ -467,79 +782,101 @@ pub fn cartesian_to_corrdinates(
 
 
 /// Opens a star file and returns a file object
-pub fn open_star_file(file_path: &str) -> Result<File, Box<dyn Error>> {
+pub fn open_star_file(file_path: &str) -> Result<File, SatSightError> {
     let file_path = Path::new(file_path);
     let file = File::open(file_path)?;
     Ok(file)
 }
 
-/// Parses a star file and returns a vector of stars
-/// | HR | Longitude | Latitude | Magnitude |
-/// |----|-----------|----------|-----------|
-pub fn parse_star_file(file: File) -> Result<Vec<Star>, Box<dyn Error>> {
-    let mut reader = Reader::from_reader(file);
+/// Parses a star file and returns a vector of stars.
+/// Columns are found by their header names (`HR`, `GLON`, `GLAT`, `Vmag`) so their order does
+/// not matter, see `catalog::CatalogSchema` for files that use other names.
+pub fn parse_star_file(file: File) -> Result<Vec<Star>, SatSightError> {
+    catalog::parse_star_file_with_schema(file, &catalog::CatalogSchema::default())
+}
 
-    // Read the CSV records
-    let mut stars: Vec<Star> = Vec::new();
-    for result in reader.records() {
-        let record = result?;
-        // Process each record
-        let mut star: Star = Star {
-            hr: 0,
-            lat: 0.0,
-            lon: 0.0,
-            mag: 0.0,
-            fingure_print: 0.0,
-        };
-        if let Some(hr) = record.get(0) {
-            star.hr = hr.parse().unwrap();
-        }
-        if let Some(lat) = record.get(2) {
-            star.lat = lat.parse().unwrap();
-        }
-        if let Some(lon) = record.get(1) {
-            star.lon = lon.parse().unwrap();
-        }
-        if let Some(mag) = record.get(3) {
-            star.mag = mag.parse().unwrap();
-        }
-        stars.push(star);
-    }
-    Ok(stars)
+/// Parses a star vector file and returns a vector of unit star vectors.
+/// The `x`, `y` and `z` columns can have any length, e.g. the radius 15 vectors of `vec_rep.csv`.
+/// The optional `hr` and `mag` columns default to 0 and the optional `band` column to V.
+/// | x | y | z | hr | mag | band |
+/// |---|---|---|----|-----|------|
+pub fn parse_star_vec_file(file: File) -> Result<Vec<StarVec>, SatSightError> {
+    parse_star_vecs(file)
 }
 
-pub fn parse_star_vec_file(file: File) -> Result<Vec<StarVec>, Box<dyn Error>> {
-    let mut reader = Reader::from_reader(file);
+fn parse_star_vecs<R: Read>(input: R) -> Result<Vec<StarVec>, SatSightError> {
+    let mut reader = Reader::from_reader(input);
+    let headers = reader.headers()?.clone();
+    let columns = [
+        catalog::require_column(&headers, "x")?,
+        catalog::require_column(&headers, "y")?,
+        catalog::require_column(&headers, "z")?,
+    ];
+    let hr_column = catalog::find_column(&headers, "hr");
+    let mag_column = catalog::find_column(&headers, "mag");
+    let band_column = catalog::find_column(&headers, "band");
 
     // Read the CSV records
     let mut stars: Vec<StarVec> = Vec::new();
     for result in reader.records() {
         let record = result?;
+        let line = record.position().map_or(0, |position| position.line());
         // Process each record
-        let mut star: StarVec = StarVec {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-        };
-        if let Some(x) = record.get(0) {
-            star.x = x.parse().unwrap();
-        }
-        if let Some(y) = record.get(1) {
-            star.y = y.parse().unwrap();
-        }
-        if let Some(z) = record.get(2) {
-            star.z = z.parse().unwrap();
+        let mut components = [0.0_f64; 3];
+        for ((component, column), name) in components.iter_mut().zip(columns).zip(["x", "y", "z"]) {
+            *component = catalog::parse_csv_field(&record, column, name)?
+                .ok_or_else(|| SatSightError::parse(line, name, "missing value"))?;
         }
-        stars.push(star);
+        let hr = match hr_column {
+            Some(column) => catalog::parse_csv_field(&record, column, "hr")?.unwrap_or(0),
+            None => 0,
+        };
+        let mag = match mag_column {
+            Some(column) => catalog::parse_csv_field(&record, column, "mag")?.unwrap_or(0.0),
+            None => 0.0,
+        };
+        let band = match band_column.and_then(|column| record.get(column)).filter(|text| !text.trim().is_empty()) {
+            Some(text) => MagnitudeBand::from_name(text)
+                .ok_or_else(|| SatSightError::parse(line, "band", format!("unknown band '{}'", text)))?,
+            None => MagnitudeBand::V,
+        };
+        let star_vec = StarVec::new(hr, components[0], components[1], components[2], mag)
+            .map_err(|_| SatSightError::parse(line, "x", "star vector has no direction"))?;
+        stars.push(StarVec { band, ..star_vec });
     }
     Ok(stars)
 }
 
-pub fn increase_contrast(image: &mut GrayImage) {
+/// Converts stars into unit star vectors, e.g. to regenerate `vec_rep.csv` from a catalog
+pub fn stars_to_star_vecs(stars: &[Star]) -> Vec<StarVec> {
+    stars.iter().map(StarVec::from).collect()
+}
+
+/// Writes star vectors as a csv file that `parse_star_vec_file` reads back
+pub fn write_star_vec_file(file: File, star_vecs: &[StarVec]) -> Result<(), SatSightError> {
+    write_star_vecs(file, star_vecs)
+}
+
+fn write_star_vecs<W: Write>(output: W, star_vecs: &[StarVec]) -> Result<(), SatSightError> {
+    let mut writer = csv::Writer::from_writer(output);
+    for star_vec in star_vecs {
+        writer.serialize(star_vec)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Stretches the pixel values of the image to the full 0-255 range.
+/// A flat image has no contrast to stretch, it is left untouched and `SatSightError::FlatImage`
+/// is returned.
+pub fn increase_contrast(image: &mut GrayImage) -> Result<(), SatSightError> {
     // Step 1: Find the minimum and maximum pixel values
     let (min_pixel, max_pixel) = image.iter().fold((u8::MAX, 0), |(min, max), &pixel| {
         (min.min(pixel), max.max(pixel))
     });
+    if max_pixel <= min_pixel {
+        return Err(SatSightError::FlatImage);
+    }
 
     // Step 2: Compute the contrast stretch factor
     let contrast_factor = 255.0 / f64::from(max_pixel - min_pixel);
@@ -547,56 +884,62 @@ pub fn increase_contrast(image: &mut GrayImage) {
     // Step 3: Apply the contrast stretch to each pixel
     for pixel in image.iter_mut() {
         // Calculate the new pixel value after applying the contrast stretch
-        *pixel = (((*pixel as f64 - f64::from(min_pixel)) * contrast_factor) as u8).min(255);
+        *pixel = ((*pixel as f64 - f64::from(min_pixel)) * contrast_factor) as u8;
     }
+    Ok(())
 }
 
 
-pub fn get_stars_from_image(img: &GrayImage) -> Result<Vec<Star>, Box<dyn Error>> {
+pub fn get_stars_from_image(img: &GrayImage) -> Result<Vec<Star>, SatSightError> {
     let (image_width, image_height) = img.dimensions();
     //println!("width: {}, height: {}", image_width, image_height);
     let mut visited_pixels = vec![vec![false; image_width as usize]; image_height as usize]; // Keep track of visited pixels
     let mut stars: Vec<Star> = Vec::new();
 
-    fn dfs(
-        img: &GrayImage,
-        x: u32,
-        y: u32,
-        visited_pixels: &mut Vec<Vec<bool>>,
-        image_width: u32,
-        image_height: u32,
-    ) {
-        if x <= 0
-            || y <= 0
-            || x >= image_width
-            || y >= image_height
-            || visited_pixels[y as usize][x as usize] == true
-            || img.get_pixel(x, y)[0] < 250
-        {
-            return;
-        }
+    // Marks every white pixel connected to (x, y) as visited.
+    // Uses an explicit stack so a large bright region can not overflow the call stack.
+    fn flood_fill(img: &GrayImage, x: u32, y: u32, visited_pixels: &mut [Vec<bool>]) {
+        let (image_width, image_height) = img.dimensions();
+        let mut stack = vec![(x, y)];
+
+        while let Some((x, y)) = stack.pop() {
+            if visited_pixels[y as usize][x as usize] || img.get_pixel(x, y)[0] < 250 {
+                continue;
+            }
 
-        visited_pixels[y as usize][x as usize] = true;
+            visited_pixels[y as usize][x as usize] = true;
 
-        // Explore adjacent white pixels
-        dfs(img, x + 1, y, visited_pixels, image_width, image_height);
-        dfs(img, x - 1, y, visited_pixels, image_width, image_height);
-        dfs(img, x, y + 1, visited_pixels, image_width, image_height);
-        dfs(img, x, y - 1, visited_pixels, image_width, image_height);
+            // Explore adjacent white pixels
+            if x + 1 < image_width {
+                stack.push((x + 1, y));
+            }
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if y + 1 < image_height {
+                stack.push((x, y + 1));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+        }
     }
 
     for y in 0..image_height {
         for x in 0..image_width {
             if !visited_pixels[y as usize][x as usize] && img.get_pixel(x, y)[0] >= 250 {
                 //println!("Found star at x: {}, y: {}", x, y);
-                dfs(img, x, y, &mut visited_pixels, image_width, image_height);
+                flood_fill(img, x, y, &mut visited_pixels);
                 //let (lat, lon) = cartesian_to_corrdinates(x, y, image_width, image_height, VIEWPORT_DEG);
                 stars.push(Star {
                     hr: 0,
-                    lat: x as f32,
-                    lon: y as f32,
+                    id: StarId::Unknown,
+                    lat: x as f64,
+                    lon: y as f64,
                     mag: 0.0,
+                    band: MagnitudeBand::Sensor,
                     fingure_print: 0.0,
+                    flags: 0,
                 });
                 //println!("lat: {}, lon: {}", x, y);
             }
@@ -605,6 +948,258 @@ pub fn get_stars_from_image(img: &GrayImage) -> Result<Vec<Star>, Box<dyn Error>
     Ok(stars)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat_sight::camera::CameraModel;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    const ARCSEC: f64 = PI / (180.0 * 3600.0);
+
+    fn star_at(lat: f64, lon: f64) -> Star {
+        Star {
+            hr: 0,
+            id: StarId::Unknown,
+            lat,
+            lon,
+            mag: 0.0,
+            band: MagnitudeBand::V,
+            fingure_print: 0.0,
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn render_needs_sensor_magnitudes() {
+        let camera = CameraModel::default();
+        let attitude = Attitude::from_ra_dec_roll(0.0, 0.0, 0.0);
+        let mut image = GrayImage::new(camera.width, camera.height);
+        let result = render_stars(&mut image, &[star_at(0.0, 0.0)], &attitude, &camera, 1.0, 7.5);
+        assert!(matches!(result, Err(SatSightError::WrongMagnitudeBand(_))));
+        assert!(image.iter().all(|pixel| *pixel == 0));
+
+        let sensor = Star { band: MagnitudeBand::Sensor, ..star_at(0.0, 0.0) };
+        render_stars(&mut image, &[sensor], &attitude, &camera, 1.0, 7.5).unwrap();
+        assert!(image.iter().any(|pixel| *pixel > 0));
+    }
+
+    #[test]
+    fn samples_between_pixel_centres_are_weighted_averages() {
+        let mut image = GrayImage::new(4, 4);
+        image.put_pixel(1, 1, image::Luma([100]));
+        image.put_pixel(2, 1, image::Luma([200]));
+
+        assert_eq!(sample_image(&image, (1.5, 1.5)), Some(100.0));
+        // Half a pixel right of the centre of (1, 1) is half way to (2, 1)
+        assert_eq!(sample_image(&image, (2.0, 1.5)), Some(150.0));
+        assert_eq!(sample_image(&image, (1.75, 1.5)), Some(125.0));
+        // Between four pixel centres, two of them dark
+        assert_eq!(sample_image(&image, (2.0, 2.0)), Some(75.0));
+    }
+
+    #[test]
+    fn samples_off_the_image_are_none() {
+        let image = GrayImage::from_pixel(4, 3, image::Luma([80]));
+        for pixel in [(-0.1, 1.0), (4.0, 1.0), (1.0, -0.5), (1.0, 3.0), (f64::NAN, 1.0)] {
+            assert_eq!(sample_image(&image, pixel), None, "{:?}", pixel);
+        }
+        // Inside the image the edge pixels fade out over their outer half
+        assert_eq!(sample_image(&image, (0.5, 0.5)), Some(80.0));
+        assert_eq!(sample_image(&image, (0.0, 1.5)), Some(40.0));
+        assert_eq!(sample_image(&image, (0.0, 0.0)), Some(20.0));
+    }
+
+    #[test]
+    fn bilinear_weights_sum_to_one_inside_the_image() {
+        let image = GrayImage::new(4, 4);
+        let neighbours: Vec<((u32, u32), f64)> = bilinear_neighbours(&image, (1.75, 2.25)).collect();
+        assert_eq!(neighbours, [((1, 1), 0.1875), ((2, 1), 0.0625), ((1, 2), 0.5625), ((2, 2), 0.1875)]);
+        // Only the corner pixel is left at the corner of the image
+        assert_eq!(bilinear_neighbours(&image, (0.0, 0.0)).collect::<Vec<_>>(), [((0, 0), 0.25)]);
+    }
+
+    #[test]
+    fn rendered_stars_are_centred_on_their_pixel() {
+        let camera = CameraModel::default();
+        let attitude = Attitude::from_ra_dec_roll(0.0, 0.0, 0.0);
+        let star = Star { band: MagnitudeBand::Sensor, mag: 1.0, ..star_at(0.123, 0.456) };
+        let pixel = project_stars(std::slice::from_ref(&star), &attitude, &camera)[0].pixel;
+
+        let mut image = GrayImage::new(camera.width, camera.height);
+        render_stars(&mut image, &[star], &attitude, &camera, 1.0, 7.5).unwrap();
+        let (mut total, mut x, mut y) = (0.0, 0.0, 0.0);
+        for (column, row, value) in image.enumerate_pixels() {
+            let value = value[0] as f64;
+            total += value;
+            x += value * (column as f64 + 0.5);
+            y += value * (row as f64 + 0.5);
+        }
+        // A saturated star spreads its 255 over four pixels, rounding each
+        assert!((total - 255.0).abs() <= 2.0);
+        assert!((x / total - pixel.0).abs() < 0.01 && (y / total - pixel.1).abs() < 0.01, "{} {} {:?}", x / total, y / total, pixel);
+    }
+
+    #[test]
+    fn stars_survive_the_trip_through_star_vectors() {
+        for (lat, lon) in [(0.0, 0.0), (-16.88, 114.44), (45.5, 359.99), (-89.5, 180.0), (12.3, 0.001)] {
+            let star = Star { hr: 42, mag: 3.25, band: MagnitudeBand::Sensor, ..star_at(lat, lon) };
+            let star_vec = StarVec::from(&star);
+            assert!((star_vec.to_vector().norm() - 1.0).abs() < 1e-15);
+
+            let (lat_back, lon_back) = xyz_to_lat_lon(star_vec.x, star_vec.y, star_vec.z).unwrap();
+            assert!((lat_back - lat).abs() < 1e-12 && (lon_back - lon).abs() < 1e-9, "{} {}", lat_back, lon_back);
+            let back = star_vec.to_star();
+            assert_eq!((back.hr, back.id, back.mag, back.band), (42, StarId::Hr(42), 3.25, MagnitudeBand::Sensor));
+            assert_eq!((back.lat, back.lon), (lat_back, lon_back));
+        }
+        assert!(xyz_to_lat_lon(0.0, 0.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn star_vec_files_read_back_what_was_written() {
+        let star_vecs = vec![
+            StarVec::new(1, 15.0, 0.0, 0.0, 6.7).unwrap(),
+            StarVec { band: MagnitudeBand::Sensor, ..StarVec::new(0, -0.3, 0.4, -1.2, -1.46).unwrap() },
+        ];
+        let mut bytes = Vec::new();
+        write_star_vecs(&mut bytes, &star_vecs).unwrap();
+        let read = parse_star_vecs(bytes.as_slice()).unwrap();
+
+        assert_eq!(read.len(), 2);
+        for (read, written) in read.iter().zip(&star_vecs) {
+            // Reading normalises the vector again, which can move the last bit
+            assert!((read.to_vector() - written.to_vector()).norm() < 1e-15);
+            assert_eq!((read.hr, read.mag, read.band), (written.hr, written.mag, written.band));
+        }
+        // Files without the optional columns, like vec_rep.csv
+        let read = parse_star_vecs("x,y,z\n0,0,15\n".as_bytes()).unwrap();
+        assert_eq!((read[0].hr, read[0].z, read[0].mag, read[0].band), (0, 1.0, 0.0, MagnitudeBand::V));
+    }
+
+    /// Checks that a star with `flag` is left out by every filtered selection and the rest kept
+    fn assert_flag_is_filtered(flag: u32) {
+        let flags = [0, FLAG_VARIABLE, FLAG_MULTIPLE, FLAG_HIGH_PROPER_MOTION, FLAG_MISSING_PHOTOMETRY];
+        let stars: Vec<Star> = flags
+            .iter()
+            .enumerate()
+            .map(|(i, flags)| Star { hr: i as u32 + 1, flags: *flags, ..star_at(0.1 * i as f64, 0.1) })
+            .collect();
+        let expected: Vec<u32> = stars.iter().filter(|star| star.flags != flag).map(|star| star.hr).collect();
+        let hrs = |stars: Vec<Star>| stars.iter().map(|star| star.hr).collect::<Vec<u32>>();
+
+        let filter = StarFilter { exclude_flags: flag };
+        assert!(stars.iter().all(|star| filter.allows(star) == (star.flags != flag)));
+        assert_eq!(hrs(filter.apply(stars.clone())), expected);
+        assert_eq!(hrs(viewable_stars_filtered((0.0, 0.0), stars.clone(), 5.0, &filter)), expected);
+        let attitude = Attitude::from_ra_dec_roll(0.0, 0.0, 0.0);
+        assert_eq!(hrs(frustum_stars_filtered(stars.clone(), &attitude, &CameraModel::default(), 0.0, &filter)), expected);
+
+        // Without a filter every star is in view
+        assert_eq!(frustum_stars(stars.clone(), &attitude, &CameraModel::default(), 0.0).len(), flags.len());
+        // Of the flagged stars the photometric filter keeps only the high proper motion ones
+        let flagged = stars.iter().find(|star| star.flags == flag).unwrap();
+        assert_eq!(StarFilter::photometric().allows(flagged), flag == FLAG_HIGH_PROPER_MOTION);
+    }
+
+    #[test]
+    fn variable_stars_are_filtered() {
+        assert_flag_is_filtered(FLAG_VARIABLE);
+    }
+
+    #[test]
+    fn multiple_stars_are_filtered() {
+        assert_flag_is_filtered(FLAG_MULTIPLE);
+    }
+
+    #[test]
+    fn high_proper_motion_stars_are_filtered() {
+        assert_flag_is_filtered(FLAG_HIGH_PROPER_MOTION);
+    }
+
+    #[test]
+    fn stars_without_photometry_are_filtered() {
+        assert_flag_is_filtered(FLAG_MISSING_PHOTOMETRY);
+    }
+
+    #[test]
+    fn angular_separation_of_known_pairs() {
+        assert!((angular_separation(0.0, 0.0, 0.0, FRAC_PI_2) - FRAC_PI_2).abs() < 1e-15);
+        assert!((angular_separation(0.0, 0.0, FRAC_PI_2, 1.0) - FRAC_PI_2).abs() < 1e-15);
+        // Across the pole, 30 degrees to it and 30 degrees back down
+        let lat = 60_f64.to_radians();
+        assert!((angular_separation(lat, 0.0, lat, PI) - PI / 3.0).abs() < 1e-15);
+        // Along a meridian the separation is the difference in latitude
+        let (phi1, phi2) = (0.3, 0.3 + ARCSEC);
+        assert!((angular_separation(phi1, 2.0, phi2, 2.0) / (phi2 - phi1) - 1.0).abs() < 1e-9);
+        // Symmetric in its two points
+        assert_eq!(angular_separation(0.1, 0.2, -0.7, 2.5), angular_separation(-0.7, 2.5, 0.1, 0.2));
+    }
+
+    #[test]
+    fn angular_separation_near_zero_and_antipodal() {
+        // A milliarcsecond apart, where acos of a dot product gives 0
+        let separation = angular_separation(0.4, 0.0, 0.4, 1e-3 * ARCSEC / 0.4_f64.cos());
+        assert!((separation / (1e-3 * ARCSEC) - 1.0).abs() < 1e-9, "{}", separation);
+        assert_eq!(angular_separation(0.4, 1.0, 0.4, 1.0), 0.0);
+
+        assert!((angular_separation(0.2, 0.3, -0.2, 0.3 + PI) - PI).abs() < 1e-15);
+        assert!((angular_separation(FRAC_PI_2, 0.0, -FRAC_PI_2, 0.0) - PI).abs() < 1e-15);
+        // A milliarcsecond short of antipodal, where the haversine loses its digits
+        let separation = angular_separation(0.0, 0.0, 0.0, PI - 1e-3 * ARCSEC);
+        assert!(((PI - separation) / (1e-3 * ARCSEC) - 1.0).abs() < 1e-6, "{}", PI - separation);
+    }
 
+    #[test]
+    fn sky_projections_match_the_camera_orientation() {
+        let camera = CameraModel::default();
+        let attitude = Attitude::from_ra_dec_roll(0.0, 0.0, 0.0);
+        let f = camera.focal_length_pixels();
+        let star = star_at(1.0, 1.0); // 1 degree east and north of the boresight
+        let pixel = project_stars(std::slice::from_ref(&star), &attitude, &camera)[0].pixel;
+        let (x, y) = gnomonic_porjection(0.0, 0.0, 1_f64.to_radians(), 1_f64.to_radians()).unwrap();
+
+        assert!(x > 0.0 && y > 0.0 && pixel.0 > camera.principal_point.0 && pixel.1 > camera.principal_point.1);
+        assert!((x * f + camera.principal_point.0 - pixel.0).abs() < 1e-9, "{} {:?}", x, pixel);
+        assert!((y * f + camera.principal_point.1 - pixel.1).abs() < 1e-9, "{} {:?}", y, pixel);
+    }
 
+    #[test]
+    fn roll_turns_the_sky_about_the_boresight() {
+        let camera = CameraModel::default();
+        let (cx, cy) = camera.principal_point;
+        let offset = camera.focal_length_pixels() * 1_f64.to_radians().tan();
+        let pixel = |star: Star, roll: f64| {
+            project_stars(&[star], &Attitude::from_ra_dec_roll(0.0, 0.0, roll), &camera)[0].pixel
+        };
+
+        // East is to the right at zero roll
+        let east = pixel(star_at(0.0, 1.0), 0.0);
+        assert!((east.0 - (cx + offset)).abs() < 1e-9 && (east.1 - cy).abs() < 1e-9, "{:?}", east);
+        // Rolled by 90 degrees the camera x axis points north, so east moves onto the vertical
+        // axis above the centre and north onto the horizontal axis to the right
+        let east = pixel(star_at(0.0, 1.0), 90.0);
+        assert!((east.0 - cx).abs() < 1e-9 && (east.1 - (cy - offset)).abs() < 1e-9, "{:?}", east);
+        let north = pixel(star_at(1.0, 0.0), 90.0);
+        assert!((north.0 - (cx + offset)).abs() < 1e-9 && (north.1 - cy).abs() < 1e-9, "{:?}", north);
+    }
 
+    #[test]
+    fn azimuthal_projections_invert() {
+        let (lambda_view, phi_view) = (1.2, -0.4);
+        for (lambda, phi) in [(1.3, -0.35), (0.9, -0.6), (1.2, 0.2), (1.25, -0.4)] {
+            for lens in [LensProjection::Gnomonic, LensProjection::Orthographic, LensProjection::Stereographic, LensProjection::Equidistant] {
+                let (x, y) = azimuthal_projection(lens, lambda_view, phi_view, lambda, phi).unwrap();
+                let (lambda_back, phi_back) = inverse_azimuthal_projection(lens, lambda_view, phi_view, x, y).unwrap();
+                assert!((lambda_back - lambda).abs() < 1e-12 && (phi_back - phi).abs() < 1e-12, "{:?} ({}, {})", lens, lambda, phi);
+            }
+        }
+    }
+
+    #[test]
+    fn star_distance_is_in_radians() {
+        let distance = calculate_distance_between_stars(&star_at(10.0, 20.0), &star_at(10.0 + 1.0 / 3600.0, 20.0));
+        assert!((distance / ARCSEC - 1.0).abs() < 1e-9, "{}", distance);
+        let distance = calculate_distance_between_stars(&star_at(0.0, 0.0), &star_at(0.0, 90.0));
+        assert!((distance - FRAC_PI_2).abs() < 1e-15);
+    }
+}
@@ -0,0 +1,324 @@
+//! Time keeping and apparent place corrections for catalog stars.
+//!
+//! Catalog positions are given for the J2000.0 epoch. A mission flying decades
+//! later sees high proper motion stars well away from their catalog position,
+//! so the catalog is propagated to the observation epoch before it is projected.
+//! The observer's motion shifts apparent positions by up to ~25" through
+//! aberration, which is applied to the catalog in the same way.
+
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+
+use super::catalog::BscStar;
+use super::frames::{convert_vector, equatorial_to_galactic, lat_lon_to_unit_vector, unit_vector_to_lat_lon, Frame};
+use super::photometry::MagnitudeBand;
+use super::{Star, StarId};
+
+/// Julian date of the J2000.0 epoch (2000 January 1, 12h TT)
+pub const J2000_JD: f64 = 2_451_545.0;
+/// Days in a Julian year
+pub const DAYS_PER_JULIAN_YEAR: f64 = 365.25;
+/// One arcsecond in radians
+pub const ARCSEC: f64 = std::f64::consts::PI / 648_000.0;
+
+// ==== Time ======================================================================================
+// Conversions between calendar dates, Julian dates and Julian epochs
+// ================================================================================================
+
+/// Returns the Julian date of a Gregorian calendar date, `day` may have a fractional part
+pub fn julian_date(year: i32, month: u32, day: f64) -> f64 {
+    // Meeus, Astronomical Algorithms, chapter 7
+    let (mut y, mut m) = (year as f64, month as f64);
+    if month <= 2 {
+        y -= 1.0;
+        m += 12.0;
+    }
+    let a = (y / 100.0).floor();
+    let b = 2.0 - a + (a / 4.0).floor();
+    (365.25 * (y + 4716.0)).floor() + (30.6001 * (m + 1.0)).floor() + day + b - 1524.5
+}
+
+/// Converts a Julian date to a Julian epoch, e.g. 2000.0 for J2000
+pub fn julian_epoch(jd: f64) -> f64 {
+    2000.0 + (jd - J2000_JD) / DAYS_PER_JULIAN_YEAR
+}
+
+/// Julian centuries of TT since J2000.0, the time argument of the IAU models
+pub fn julian_centuries(jd: f64) -> f64 {
+    (jd - J2000_JD) / (100.0 * DAYS_PER_JULIAN_YEAR)
+}
+
+// ==== Proper Motion =============================================================================
+// Epoch aware star records and propagation to the epoch of date
+// ================================================================================================
+
+/// A star with an equatorial position at a known epoch and its proper motion
+#[derive(Clone, Debug)]
+pub struct EpochStar {
+    pub id: StarId,  // Catalog identifier
+    pub ra: f64,     // Right ascension at `epoch` (deg)
+    pub dec: f64,    // Declination at `epoch` (deg)
+    pub pm_ra: f64,  // Proper motion in RA, cos(dec) * d(ra)/dt (arcsec/yr)
+    pub pm_dec: f64, // Proper motion in Dec (arcsec/yr)
+    pub mag: f32,    // Magnitude
+    pub epoch: f64,  // Julian epoch of the position, 2000.0 for the BSC
+    pub flags: u32,  // Catalog quality flags, see `Star::flags`
+}
+
+impl From<&BscStar> for EpochStar {
+    fn from(star: &BscStar) -> Self {
+        EpochStar {
+            id: StarId::Hr(star.hr),
+            ra: star.ra,
+            dec: star.dec,
+            pm_ra: star.pm_ra,
+            pm_dec: star.pm_dec,
+            mag: star.vmag,
+            epoch: 2000.0,
+            flags: star.flags(),
+        }
+    }
+}
+
+impl EpochStar {
+    /// Unit vector of the position at the record's epoch
+    pub fn unit_vector(&self) -> Vector3<f64> {
+        let (ra, dec) = (self.ra.to_radians(), self.dec.to_radians());
+        Vector3::new(dec.cos() * ra.cos(), dec.cos() * ra.sin(), dec.sin())
+    }
+
+    /// Moves the star along its proper motion to `epoch` (Julian years).
+    ///
+    /// The motion is applied along the tangent plane and renormalised, which keeps
+    /// stars near the poles well behaved. Radial velocity and parallax are ignored,
+    /// their effect on BSC stars is far below a pixel over a mission lifetime.
+    pub fn propagate(&self, epoch: f64) -> EpochStar {
+        let dt = epoch - self.epoch;
+        let (ra, dec) = (self.ra.to_radians(), self.dec.to_radians());
+
+        let position = self.unit_vector();
+        // Unit vectors towards increasing RA and increasing Dec
+        let east = Vector3::new(-ra.sin(), ra.cos(), 0.0);
+        let north = Vector3::new(-dec.sin() * ra.cos(), -dec.sin() * ra.sin(), dec.cos());
+
+        let motion = (east * self.pm_ra + north * self.pm_dec) * ARCSEC;
+        let moved = (position + motion * dt).normalize();
+
+        EpochStar {
+            ra: moved.y.atan2(moved.x).to_degrees().rem_euclid(360.0),
+            dec: moved.z.clamp(-1.0, 1.0).asin().to_degrees(),
+            epoch,
+            ..self.clone()
+        }
+    }
+
+    /// Converts the star into the galactic `Star` used by the rest of `sat_sight`
+    pub fn to_star(&self) -> Star {
+        let (glon, glat) = equatorial_to_galactic(self.ra, self.dec);
+        Star {
+            hr: match self.id {
+                StarId::Hr(hr) => hr,
+                _ => 0,
+            },
+            id: self.id,
+            lat: glat,
+            lon: glon,
+            mag: self.mag,
+            band: MagnitudeBand::V,
+            fingure_print: 0.0,
+            flags: self.flags,
+        }
+    }
+}
+
+/// Propagates a catalog to the observation `epoch` (Julian years) and returns galactic stars
+/// ready for `viewable_stars` and the projections
+pub fn propagate_catalog(stars: &[EpochStar], epoch: f64) -> Vec<Star> {
+    stars.iter().map(|star| star.propagate(epoch).to_star()).collect()
+}
+
+// ==== Aberration =================================================================================
+// Relativistic stellar aberration from the observer velocity
+// =================================================================================================
+
+/// Speed of light (km/s)
+pub const SPEED_OF_LIGHT_KM_S: f64 = 299_792.458;
+/// Astronomical unit (km)
+pub const ASTRONOMICAL_UNIT_KM: f64 = 149_597_870.7;
+
+/// Velocity of the Earth around the Sun in the J2000 equatorial frame (km/s).
+///
+/// Uses the Keplerian orbit of the Earth with the mean elements of Meeus chapter 25,
+/// good to about 0.01 km/s which is 0.01" of aberration. Add the spacecraft's orbital
+/// velocity (in the same frame) to get the observer velocity for `aberrate`.
+pub fn earth_velocity(jd: f64) -> Vector3<f64> {
+    let t = julian_centuries(jd);
+    // Geometric mean longitude and mean anomaly of the Sun, eccentricity of the Earth's orbit
+    let l0 = 280.46646 + (36000.76983 + 0.0003032 * t) * t;
+    let m = (357.52911 + (35999.05029 - 0.0001537 * t) * t).to_radians();
+    let e = 0.016708634 - (0.000042037 + 0.0000001267 * t) * t;
+
+    // Equation of centre gives the true longitude, the Earth is opposite the Sun
+    let c = (1.914602 - (0.004817 + 0.000014 * t) * t) * m.sin()
+        + (0.019993 - 0.000101 * t) * (2.0 * m).sin()
+        + 0.000289 * (3.0 * m).sin();
+    // Mean equinox of date back to J2000 with the general precession in longitude
+    let precession = 5029.0966 * t / 3600.0;
+    let earth_longitude = (l0 + c + 180.0 - precession).to_radians();
+    let perihelion = (l0 - m.to_degrees() + 180.0 - precession).to_radians();
+
+    let year_seconds = 365.256363 * 86400.0;
+    let speed = 2.0 * std::f64::consts::PI * ASTRONOMICAL_UNIT_KM / (year_seconds * (1.0 - e * e).sqrt());
+    let ecliptic = Vector3::new(
+        -speed * (earth_longitude.sin() + e * perihelion.sin()),
+        speed * (earth_longitude.cos() + e * perihelion.cos()),
+        0.0,
+    );
+    convert_vector(&ecliptic, Frame::Ecliptic, Frame::Equatorial)
+}
+
+/// Applies relativistic aberration to a true direction and returns the apparent direction.
+/// `velocity` is the observer velocity in km/s in the same frame as `direction`.
+pub fn aberrate(direction: &Vector3<f64>, velocity: &Vector3<f64>) -> Vector3<f64> {
+    let u = direction.normalize();
+    let beta = velocity / SPEED_OF_LIGHT_KM_S;
+    let beta_sq = beta.norm_squared();
+    if beta_sq == 0.0 {
+        return u;
+    }
+    let gamma = 1.0 / (1.0 - beta_sq).sqrt();
+    let u_dot_beta = u.dot(&beta);
+
+    let apparent = u / gamma + beta + beta * (u_dot_beta * gamma / (1.0 + gamma));
+    apparent.normalize()
+}
+
+/// Removes relativistic aberration from an apparent direction, the exact inverse of `aberrate`
+pub fn unaberrate(apparent: &Vector3<f64>, velocity: &Vector3<f64>) -> Vector3<f64> {
+    aberrate(apparent, &-velocity)
+}
+
+/// Moves galactic catalog stars to their apparent positions before they are projected.
+/// `velocity` is the observer velocity in km/s expressed in `velocity_frame`.
+pub fn aberrate_stars(stars: &[Star], velocity: &Vector3<f64>, velocity_frame: Frame) -> Vec<Star> {
+    let velocity = convert_vector(velocity, velocity_frame, Frame::Galactic);
+    stars
+        .iter()
+        .map(|star| {
+            let apparent = aberrate(&lat_lon_to_unit_vector(star.lat, star.lon), &velocity);
+            // An aberrated unit vector is never zero length
+            let (lat, lon) = unit_vector_to_lat_lon(&apparent).unwrap_or((star.lat, star.lon));
+            Star {
+                lat,
+                lon,
+                ..star.clone()
+            }
+        })
+        .collect()
+}
+
+/// Rotates an attitude so its boresight moves from `from` to `to`
+fn rotate_boresight(attitude: &Quaternion<f64>, from: &Vector3<f64>, to: &Vector3<f64>) -> Quaternion<f64> {
+    let correction = UnitQuaternion::rotation_between(from, to).unwrap_or_else(UnitQuaternion::identity);
+    correction.into_inner() * attitude
+}
+
+/// Corrects an attitude that was solved against catalog positions without the aberration stage.
+///
+/// Such a solution points the `boresight` (camera frame axis) at the true position of the stars
+/// the camera sees, while the camera actually points at their apparent position. `attitude`
+/// rotates camera vectors into the frame `velocity` is expressed in.
+pub fn correct_attitude_aberration(attitude: &Quaternion<f64>, boresight: &Vector3<f64>, velocity: &Vector3<f64>) -> Quaternion<f64> {
+    let solved = (attitude * Quaternion::from_imag(*boresight) * attitude.conjugate()).imag();
+    rotate_boresight(attitude, &solved, &aberrate(&solved, velocity))
+}
+
+/// Inverse of `correct_attitude_aberration`, returns the attitude a solver without the
+/// aberration stage would report for the camera's true attitude
+pub fn uncorrect_attitude_aberration(attitude: &Quaternion<f64>, boresight: &Vector3<f64>, velocity: &Vector3<f64>) -> Quaternion<f64> {
+    let pointing = (attitude * Quaternion::from_imag(*boresight) * attitude.conjugate()).imag();
+    rotate_boresight(attitude, &pointing, &unaberrate(&pointing, velocity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat_sight::catalog::parse_bsc5_record;
+
+    fn angle_between(a: &Vector3<f64>, b: &Vector3<f64>) -> f64 {
+        a.cross(b).norm().atan2(a.dot(b))
+    }
+
+    // HR 8085, 61 Cyg A, from bsc5.dat: pm +4.136 +3.203 arcsec/yr
+    const HR_8085: &str = "8085 61    CygBD+38 4343 201091 70919 793   14636A V1803 Cyg210224.8+381531210654.6+384445 82.25-05.81 5.21  +1.18 +1.11 +0.65   K5V                +4.136+3.203 +.292-064V    < 17  0.8  28.7AB   6*";
+
+    fn epoch_star(ra: f64, dec: f64, pm_ra: f64, pm_dec: f64) -> EpochStar {
+        EpochStar { id: StarId::Unknown, ra, dec, pm_ra, pm_dec, mag: 5.0, epoch: 2000.0, flags: 0 }
+    }
+
+    #[test]
+    fn sixty_one_cygni_moves_over_fifty_years() {
+        let star = EpochStar::from(&parse_bsc5_record(HR_8085, 8085).unwrap().unwrap());
+        let moved = star.propagate(2050.0);
+        assert_eq!((moved.id, moved.epoch), (StarId::Hr(8085), 2050.0));
+
+        let mean_dec = ((star.dec + moved.dec) / 2.0).to_radians();
+        let d_ra = (moved.ra - star.ra) * 3600.0 * mean_dec.cos();
+        let d_dec = (moved.dec - star.dec) * 3600.0;
+        assert!((d_ra - 206.8).abs() < 0.2 && (d_dec - 160.15).abs() < 0.2, "{} {}", d_ra, d_dec);
+    }
+
+    #[test]
+    fn a_star_without_proper_motion_stays_put() {
+        for (ra, dec) in [(10.0, 20.0), (359.9, -45.0), (123.0, 89.99)] {
+            let star = epoch_star(ra, dec, 0.0, 0.0);
+            let moved = star.propagate(2100.0);
+            assert!((moved.ra - ra).abs() < 1e-9 && (moved.dec - dec).abs() < 1e-9);
+            assert_eq!(moved.epoch, 2100.0);
+        }
+    }
+
+    #[test]
+    fn propagate_catalog_moves_every_star_to_the_epoch() {
+        let stars = [epoch_star(10.0, 20.0, 0.0, 0.0), epoch_star(300.0, -60.0, 1.0, -2.0)];
+        let catalog = propagate_catalog(&stars, 2030.0);
+        assert_eq!(catalog.len(), 2);
+        for (star, propagated) in stars.iter().zip(&catalog) {
+            let expected = star.propagate(2030.0).to_star();
+            assert_eq!((propagated.lat, propagated.lon), (expected.lat, expected.lon));
+        }
+        let (glon, glat) = equatorial_to_galactic(10.0, 20.0);
+        assert!((catalog[0].lat - glat).abs() < 1e-9 && (catalog[0].lon - glon).abs() < 1e-9);
+        // 30 years of 2.24"/yr is about 67"
+        let start = stars[1].to_star();
+        let shift = angle_between(&lat_lon_to_unit_vector(catalog[1].lat, catalog[1].lon), &lat_lon_to_unit_vector(start.lat, start.lon));
+        assert!((shift / ARCSEC - 67.08).abs() < 0.05);
+    }
+
+    #[test]
+    fn aberration_of_30_km_s_is_about_20_6_arcsec() {
+        let direction = Vector3::new(1.0, 0.0, 0.0);
+        let apparent = aberrate(&direction, &Vector3::new(0.0, 30.0, 0.0));
+        assert!((angle_between(&direction, &apparent) / ARCSEC - 20.64).abs() < 0.01);
+        // Towards the velocity the star moves, along the line of sight it does not
+        assert!(apparent.y > 0.0);
+        let along = aberrate(&direction, &Vector3::new(30.0, 0.0, 0.0));
+        assert!(angle_between(&direction, &along) < 1e-15);
+    }
+
+    #[test]
+    fn unaberrate_inverts_aberrate() {
+        let velocity = Vector3::new(-29.8, -5.0, -2.2) + Vector3::new(3.1, -6.0, 2.5);
+        for direction in [Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.3, -0.5, 0.8), Vector3::new(-0.2, 0.1, -0.9)] {
+            let u = direction.normalize();
+            assert!((unaberrate(&aberrate(&u, &velocity), &velocity) - u).norm() < 1e-14);
+        }
+    }
+
+    #[test]
+    fn earth_velocity_at_j2000() {
+        // About (-0.01720, -0.00290, -0.00126) au/day in the ephemerides
+        let v = earth_velocity(J2000_JD);
+        assert!((v - Vector3::new(-29.8, -5.0, -2.2)).norm() < 0.2, "{:?}", v);
+        assert!((v.norm() - 30.3).abs() < 0.1);
+    }
+}
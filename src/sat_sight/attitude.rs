@@ -0,0 +1,432 @@
+//! Three axis camera attitude.
+//!
+//! An `Attitude` is a unit quaternion that rotates camera frame vectors into
+//! the reference frame (Hamilton algebra, applied as `q * v * q_conj` like
+//! `rotate_vector`). The camera frame has +z along the boresight, and with
+//! zero roll +x points east and +y points north on the sky.
+//!
+//! Angles follow the rest of `sat_sight`: RA, Dec and roll are in degrees,
+//! Euler and axis-angle rotations are in radians like `axis_angle_to_quaternion`.
+
+use std::ops::Mul;
+
+use nalgebra::{Matrix3, Quaternion, Rotation3, UnitQuaternion, Vector3};
+
+use super::error::SatSightError;
+use super::frames::{convert_attitude, lat_lon_to_unit_vector, rot_x, rot_y, rot_z, Frame};
+use super::{axis_angle_to_quaternion, rotation_matrix_to_quaternion};
+
+/// Largest deviation from orthonormality accepted in a DCM
+const DCM_TOLERANCE: f64 = 1e-6;
+
+/// Below this the second Euler angle is treated as gimbal lock
+const GIMBAL_LOCK_TOLERANCE: f64 = 1e-9;
+
+/// Multiplication rule a quaternion's components are written for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuaternionAlgebra {
+    Hamilton, // ij = k, as used by nalgebra and `sat_sight`
+    Jpl,      // ij = -k, Shuster's convention used in JPL and many spacecraft tools
+}
+
+/// How a quaternion is written out by another tool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuaternionConvention {
+    pub scalar_first: bool,         // [w, x, y, z] rather than [x, y, z, w]
+    pub algebra: QuaternionAlgebra, // Multiplication rule of the components
+    pub body_to_reference: bool,    // Maps camera vectors into the reference frame rather than the inverse
+}
+
+impl QuaternionConvention {
+    /// The convention of `Attitude` itself
+    pub const HAMILTON: QuaternionConvention = QuaternionConvention {
+        scalar_first: true,
+        algebra: QuaternionAlgebra::Hamilton,
+        body_to_reference: true,
+    };
+
+    /// Shuster's JPL convention: scalar last, reference to body attitude quaternion
+    pub const JPL: QuaternionConvention = QuaternionConvention {
+        scalar_first: false,
+        algebra: QuaternionAlgebra::Jpl,
+        body_to_reference: false,
+    };
+}
+
+/// Orientation of the camera relative to a reference frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attitude {
+    q: UnitQuaternion<f64>, // Rotates camera vectors into the reference frame
+}
+
+impl Default for Attitude {
+    /// Camera axes aligned with the reference axes
+    fn default() -> Self {
+        Attitude {
+            q: UnitQuaternion::identity(),
+        }
+    }
+}
+
+impl Mul for Attitude {
+    type Output = Attitude;
+
+    /// `a * b` applies `b` first, then `a`
+    fn mul(self, rhs: Attitude) -> Attitude {
+        Attitude { q: self.q * rhs.q }
+    }
+}
+
+impl Attitude {
+    // ==== Quaternions ============================================================================
+    // The native Hamilton quaternion and the adapters for other conventions
+    // =============================================================================================
+
+    /// Builds an attitude from a Hamilton quaternion rotating camera vectors into the reference
+    /// frame. The quaternion is normalised, a zero quaternion is an error.
+    pub fn from_quaternion(q: Quaternion<f64>) -> Result<Self, SatSightError> {
+        let norm = q.norm();
+        if norm == 0.0 || !norm.is_finite() {
+            return Err(SatSightError::degenerate("quaternion has zero length"));
+        }
+        Ok(Attitude {
+            q: UnitQuaternion::new_normalize(q),
+        })
+    }
+
+    /// The Hamilton quaternion rotating camera vectors into the reference frame, as used by
+    /// `frames::convert_attitude` and the aberration corrections
+    pub fn quaternion(&self) -> Quaternion<f64> {
+        self.q.into_inner()
+    }
+
+    pub fn unit_quaternion(&self) -> UnitQuaternion<f64> {
+        self.q
+    }
+
+    /// Reads quaternion components written in another tool's convention
+    pub fn from_components(components: [f64; 4], convention: QuaternionConvention) -> Result<Self, SatSightError> {
+        let [a, b, c, d] = components;
+        let q = if convention.scalar_first {
+            Quaternion::new(a, b, c, d)
+        } else {
+            Quaternion::new(d, a, b, c)
+        };
+        Ok(Attitude::from_quaternion(q)?.adapt(convention))
+    }
+
+    /// Writes the quaternion components in another tool's convention
+    pub fn to_components(&self, convention: QuaternionConvention) -> [f64; 4] {
+        let q = self.adapt(convention).q;
+        if convention.scalar_first {
+            [q.w, q.i, q.j, q.k]
+        } else {
+            [q.i, q.j, q.k, q.w]
+        }
+    }
+
+    /// Quaternion in scalar first order `[w, x, y, z]`
+    pub fn from_scalar_first(components: [f64; 4]) -> Result<Self, SatSightError> {
+        Attitude::from_components(components, QuaternionConvention::HAMILTON)
+    }
+
+    pub fn to_scalar_first(&self) -> [f64; 4] {
+        self.to_components(QuaternionConvention::HAMILTON)
+    }
+
+    /// Quaternion in scalar last order `[x, y, z, w]`
+    pub fn from_scalar_last(components: [f64; 4]) -> Result<Self, SatSightError> {
+        Attitude::from_components(components, QuaternionConvention { scalar_first: false, ..QuaternionConvention::HAMILTON })
+    }
+
+    pub fn to_scalar_last(&self) -> [f64; 4] {
+        self.to_components(QuaternionConvention { scalar_first: false, ..QuaternionConvention::HAMILTON })
+    }
+
+    /// Converts between the native quaternion and one in `convention`, the change is its own inverse.
+    ///
+    /// Switching between body to reference and reference to body conjugates the quaternion. Reading
+    /// Hamilton components with the JPL product transposes the rotation matrix, which also
+    /// conjugates. A JPL reference to body quaternion therefore has the same components as
+    /// the native one, only the scalar moves to the end.
+    fn adapt(&self, convention: QuaternionConvention) -> Attitude {
+        let jpl = convention.algebra == QuaternionAlgebra::Jpl;
+        if jpl == convention.body_to_reference {
+            self.inverse()
+        } else {
+            *self
+        }
+    }
+
+    // ==== Matrices ===============================================================================
+    // Direction cosine matrix and rotation matrix
+    // =============================================================================================
+
+    /// Rotation matrix taking camera vector components to reference frame components
+    pub fn body_to_reference(&self) -> Matrix3<f64> {
+        self.q.to_rotation_matrix().into_inner()
+    }
+
+    /// Direction cosine matrix, the passive rotation taking reference frame components to
+    /// camera components. Its rows are the camera axes expressed in the reference frame.
+    pub fn dcm(&self) -> Matrix3<f64> {
+        self.body_to_reference().transpose()
+    }
+
+    /// Builds an attitude from a direction cosine matrix (reference to camera).
+    /// The matrix has to be a proper rotation.
+    pub fn from_dcm(dcm: &Matrix3<f64>) -> Result<Self, SatSightError> {
+        let orthogonality = (dcm * dcm.transpose() - Matrix3::identity()).amax();
+        let determinant = dcm.determinant();
+        if !orthogonality.is_finite() || orthogonality > DCM_TOLERANCE || (determinant - 1.0).abs() > DCM_TOLERANCE {
+            return Err(SatSightError::degenerate("matrix is not a proper rotation"));
+        }
+        Attitude::from_quaternion(rotation_matrix_to_quaternion(&dcm.transpose()))
+    }
+
+    // ==== Euler Angles ===========================================================================
+    // 3-2-1 (yaw, pitch, roll) and 3-1-3 sequences of passive rotations, radians
+    // =============================================================================================
+
+    /// Builds an attitude from the 3-2-1 sequence: yaw about z, then pitch about the new y,
+    /// then roll about the new x. The DCM is `rot_x(roll) * rot_y(pitch) * rot_z(yaw)`.
+    pub fn from_euler_321(yaw: f64, pitch: f64, roll: f64) -> Self {
+        Attitude::from_proper_dcm(&(rot_x(roll) * rot_y(pitch) * rot_z(yaw)))
+    }
+
+    /// Returns (yaw, pitch, roll) of the 3-2-1 sequence, pitch in [-pi/2, pi/2].
+    /// At gimbal lock (pitch = +-pi/2) the roll is set to zero and folded into the yaw.
+    pub fn to_euler_321(&self) -> (f64, f64, f64) {
+        let c = self.dcm();
+        let pitch = (-c[(0, 2)]).clamp(-1.0, 1.0).asin();
+        if c[(0, 0)].hypot(c[(0, 1)]) < GIMBAL_LOCK_TOLERANCE {
+            return ((-c[(1, 0)]).atan2(c[(1, 1)]), pitch, 0.0);
+        }
+        (c[(0, 1)].atan2(c[(0, 0)]), pitch, c[(1, 2)].atan2(c[(2, 2)]))
+    }
+
+    /// Builds an attitude from the 3-1-3 sequence: `phi` about z, then `theta` about the new x,
+    /// then `psi` about the new z. The DCM is `rot_z(psi) * rot_x(theta) * rot_z(phi)`.
+    pub fn from_euler_313(phi: f64, theta: f64, psi: f64) -> Self {
+        Attitude::from_proper_dcm(&(rot_z(psi) * rot_x(theta) * rot_z(phi)))
+    }
+
+    /// Returns (phi, theta, psi) of the 3-1-3 sequence, theta in [0, pi].
+    /// At gimbal lock (theta = 0 or pi) psi is set to zero and folded into phi.
+    pub fn to_euler_313(&self) -> (f64, f64, f64) {
+        let c = self.dcm();
+        let theta = c[(2, 2)].clamp(-1.0, 1.0).acos();
+        if c[(2, 0)].hypot(c[(2, 1)]) < GIMBAL_LOCK_TOLERANCE {
+            return (c[(0, 1)].atan2(c[(0, 0)]), theta, 0.0);
+        }
+        (c[(2, 0)].atan2(-c[(2, 1)]), theta, c[(0, 2)].atan2(c[(1, 2)]))
+    }
+
+    /// The DCMs built from elementary rotations are proper rotations by construction
+    fn from_proper_dcm(dcm: &Matrix3<f64>) -> Self {
+        Attitude {
+            q: UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(dcm.transpose())),
+        }
+    }
+
+    // ==== Axis-Angle =============================================================================
+    // Rotation of the camera about a single axis, radians
+    // =============================================================================================
+
+    /// Builds an attitude that rotates camera vectors by `angle` about `axis` (any length)
+    pub fn from_axis_angle(axis: Vector3<f64>, angle: f64) -> Result<Self, SatSightError> {
+        let norm = axis.norm();
+        if norm == 0.0 || !norm.is_finite() {
+            return Err(SatSightError::degenerate("rotation axis has zero length"));
+        }
+        Attitude::from_quaternion(axis_angle_to_quaternion(axis / norm, angle))
+    }
+
+    /// Returns the unit rotation axis and the angle in [0, pi], the axis is +z for no rotation
+    pub fn to_axis_angle(&self) -> (Vector3<f64>, f64) {
+        self.q
+            .axis_angle()
+            .map_or((Vector3::z(), 0.0), |(axis, angle)| (axis.into_inner(), angle))
+    }
+
+    // ==== Pointing ===============================================================================
+    // Boresight direction and roll about it, degrees
+    // =============================================================================================
+
+    /// Builds the attitude of a camera looking at (ra, dec) and rolled by `roll` about the
+    /// boresight. Positive roll turns the camera +x axis from east towards north. In frames
+    /// other than equatorial, `ra`/`dec` are that frame's longitude and latitude.
+    pub fn from_ra_dec_roll(ra: f64, dec: f64, roll: f64) -> Self {
+        let (east, north, boresight) = sky_axes(ra, dec);
+        let (s, c) = roll.to_radians().sin_cos();
+        let x = east * c + north * s;
+        let y = north * c - east * s;
+        Attitude::from_proper_dcm(&Matrix3::from_rows(&[x.transpose(), y.transpose(), boresight.transpose()]))
+    }
+
+    /// Returns (ra, dec, roll) in degrees, ra and roll in [0, 360).
+    /// Looking at a pole the ra is taken from the roll reference so the result still round-trips.
+    pub fn to_ra_dec_roll(&self) -> (f64, f64, f64) {
+        let m = self.body_to_reference();
+        let (x, boresight) = (m.column(0).into_owned(), m.column(2).into_owned());
+        // atan2 rather than asin keeps full precision next to the poles
+        let dec = boresight.z.atan2(boresight.x.hypot(boresight.y)).to_degrees();
+        let ra = if boresight.x.hypot(boresight.y) < GIMBAL_LOCK_TOLERANCE {
+            0.0
+        } else {
+            boresight.y.atan2(boresight.x).to_degrees().rem_euclid(360.0)
+        };
+        let (east, north, _) = sky_axes(ra, dec);
+        let roll = x.dot(&north).atan2(x.dot(&east)).to_degrees().rem_euclid(360.0);
+        (ra, dec, roll)
+    }
+
+    /// Direction of the boresight as (lat, lon) in degrees, the `looking_direction` of
+    /// `viewable_stars` and `get_pix`
+    pub fn looking_direction(&self) -> (f64, f64) {
+        let (ra, dec, _) = self.to_ra_dec_roll();
+        (dec, ra)
+    }
+
+    // ==== Operations =============================================================================
+    // Applying, inverting and re-expressing attitudes
+    // =============================================================================================
+
+    /// Rotates a camera frame vector into the reference frame
+    pub fn rotate(&self, v: &Vector3<f64>) -> Vector3<f64> {
+        self.q * v
+    }
+
+    /// Rotates a reference frame vector into the camera frame
+    pub fn inverse_rotate(&self, v: &Vector3<f64>) -> Vector3<f64> {
+        self.q.inverse_transform_vector(v)
+    }
+
+    pub fn inverse(&self) -> Attitude {
+        Attitude { q: self.q.inverse() }
+    }
+
+    /// Smallest rotation angle (radians) between two attitudes
+    pub fn angle_to(&self, other: &Attitude) -> f64 {
+        self.q.angle_to(&other.q)
+    }
+
+    /// Re-expresses the attitude relative to another reference frame
+    pub fn convert_frame(&self, from: Frame, to: Frame) -> Attitude {
+        Attitude {
+            q: UnitQuaternion::new_normalize(convert_attitude(&self.quaternion(), from, to)),
+        }
+    }
+}
+
+/// Unit vectors towards east, north and the point (ra, dec) itself
+fn sky_axes(ra: f64, dec: f64) -> (Vector3<f64>, Vector3<f64>, Vector3<f64>) {
+    let ra_rad = ra.to_radians();
+    let boresight = lat_lon_to_unit_vector(dec, ra);
+    let east = Vector3::new(-ra_rad.sin(), ra_rad.cos(), 0.0);
+    (east, boresight.cross(&east), boresight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    fn assert_same(a: &Attitude, b: &Attitude) {
+        assert!(a.angle_to(b) < 1e-12, "{:?} and {:?}", a, b);
+    }
+
+    #[test]
+    fn euler_321_round_trips() {
+        for (yaw, pitch, roll) in [(0.3, -0.2, 1.1), (-2.9, 1.4, -0.5), (3.0, -1.5, 2.0), (0.0, 0.0, 0.0)] {
+            let attitude = Attitude::from_euler_321(yaw, pitch, roll);
+            assert!((attitude.dcm() - rot_x(roll) * rot_y(pitch) * rot_z(yaw)).amax() < 1e-14);
+            let (yaw_back, pitch_back, roll_back) = attitude.to_euler_321();
+            assert!((yaw_back - yaw).abs() < 1e-12 && (pitch_back - pitch).abs() < 1e-12 && (roll_back - roll).abs() < 1e-12);
+        }
+
+        // At gimbal lock the roll folds into the yaw and the attitude is unchanged
+        let locked = Attitude::from_euler_321(0.4, FRAC_PI_2, 0.3);
+        let (yaw, pitch, roll) = locked.to_euler_321();
+        assert_eq!(roll, 0.0);
+        assert!((pitch - FRAC_PI_2).abs() < 1e-7);
+        assert_same(&Attitude::from_euler_321(yaw, pitch, roll), &locked);
+    }
+
+    #[test]
+    fn euler_313_round_trips() {
+        for (phi, theta, psi) in [(0.3, 0.2, 1.1), (-2.9, 3.0, -0.5), (1.0, FRAC_PI_2, -3.0)] {
+            let attitude = Attitude::from_euler_313(phi, theta, psi);
+            assert!((attitude.dcm() - rot_z(psi) * rot_x(theta) * rot_z(phi)).amax() < 1e-14);
+            let (phi_back, theta_back, psi_back) = attitude.to_euler_313();
+            assert!((phi_back - phi).abs() < 1e-12 && (theta_back - theta).abs() < 1e-12 && (psi_back - psi).abs() < 1e-12);
+        }
+
+        for theta in [0.0, PI] {
+            let locked = Attitude::from_euler_313(0.4, theta, 0.3);
+            let (phi, theta_back, psi) = locked.to_euler_313();
+            assert_eq!(psi, 0.0);
+            assert!((theta_back - theta).abs() < 1e-7);
+            assert_same(&Attitude::from_euler_313(phi, theta_back, psi), &locked);
+        }
+    }
+
+    #[test]
+    fn ra_dec_roll_round_trips_including_the_poles() {
+        for (ra, dec, roll) in [(10.0, 20.0, 30.0), (359.5, -45.0, 0.0), (180.0, 0.0, 270.0)] {
+            let attitude = Attitude::from_ra_dec_roll(ra, dec, roll);
+            let boresight = attitude.rotate(&Vector3::z());
+            assert!((boresight - lat_lon_to_unit_vector(dec, ra)).norm() < 1e-14);
+            let (ra_back, dec_back, roll_back) = attitude.to_ra_dec_roll();
+            assert!((ra_back - ra).abs() < 1e-9 && (dec_back - dec).abs() < 1e-9 && (roll_back - roll).abs() < 1e-9);
+        }
+
+        // At a pole ra and roll both turn about the boresight, only their sum is defined
+        for dec in [90.0, -90.0] {
+            let attitude = Attitude::from_ra_dec_roll(40.0, dec, 25.0);
+            let (ra, dec_back, roll) = attitude.to_ra_dec_roll();
+            assert_eq!(ra, 0.0);
+            assert!((dec_back - dec).abs() < 1e-9);
+            assert_same(&Attitude::from_ra_dec_roll(ra, dec_back, roll), &attitude);
+        }
+    }
+
+    #[test]
+    fn jpl_quaternions_are_scalar_last_and_reference_to_body() {
+        // A quarter turn about z that takes the camera x axis to the reference y axis
+        let attitude = Attitude::from_axis_angle(Vector3::z(), FRAC_PI_2).unwrap();
+        assert!((attitude.rotate(&Vector3::x()) - Vector3::y()).norm() < 1e-15);
+
+        let (s, c) = FRAC_PI_4.sin_cos();
+        let expected_hamilton = [c, 0.0, 0.0, s];
+        let expected_jpl = [0.0, 0.0, s, c];
+        for (found, expected) in attitude.to_scalar_first().iter().zip(expected_hamilton) {
+            assert!((found - expected).abs() < 1e-15);
+        }
+        for (found, expected) in attitude.to_components(QuaternionConvention::JPL).iter().zip(expected_jpl) {
+            assert!((found - expected).abs() < 1e-15);
+        }
+        assert_same(&Attitude::from_components(expected_jpl, QuaternionConvention::JPL).unwrap(), &attitude);
+
+        // Hamilton reference to body is the conjugate
+        let reference_to_body = QuaternionConvention { body_to_reference: false, ..QuaternionConvention::HAMILTON };
+        let conjugate = attitude.to_components(reference_to_body);
+        assert!((conjugate[3] + s).abs() < 1e-15 && (conjugate[0] - c).abs() < 1e-15);
+    }
+
+    #[test]
+    fn dcm_rows_are_the_camera_axes() {
+        let attitude = Attitude::from_ra_dec_roll(75.0, -30.0, 40.0);
+        let dcm = attitude.dcm();
+        for (row, axis) in [Vector3::x(), Vector3::y(), Vector3::z()].iter().enumerate() {
+            assert!((dcm.row(row).transpose() - attitude.rotate(axis)).norm() < 1e-14);
+        }
+        assert!((dcm * attitude.rotate(&Vector3::z()) - Vector3::z()).norm() < 1e-14);
+        assert!((attitude.body_to_reference() * dcm - Matrix3::identity()).amax() < 1e-14);
+        assert_same(&Attitude::from_dcm(&dcm).unwrap(), &attitude);
+
+        // Reflections and skewed matrices are not attitudes
+        assert!(Attitude::from_dcm(&(-Matrix3::identity())).is_err());
+        assert!(Attitude::from_dcm(&Matrix3::new(1.0, 0.1, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0)).is_err());
+    }
+}
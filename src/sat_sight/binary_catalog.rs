@@ -0,0 +1,455 @@
+//! Compact binary star catalog for flight loading.
+//!
+//! Parsing the csv catalogs at boot is slow and needs a text parser on the
+//! flight computer. This format is a fixed size little-endian header followed
+//! by fixed size records, so the file can be memory-mapped (or embedded with
+//! `include_bytes!`) and records read in place without parsing the whole file.
+//!
+//! Header (32 bytes)
+//! | Offset | Type     | Field                                        |
+//! |--------|----------|----------------------------------------------|
+//! | 0      | [u8; 8]  | Magic `SATSCAT\0`                            |
+//! | 8      | u16      | Format version                               |
+//! | 10     | u16      | Header size in bytes                         |
+//! | 12     | u16      | Record size in bytes                         |
+//! | 14     | u16      | Frame code: 0 eq, 1 gal, 2 ecl               |
+//! | 16     | u32      | Number of records                            |
+//! | 20     | u32      | CRC-32 (IEEE) of the record bytes            |
+//! | 24     | f64      | Julian epoch of the positions                |
+//!
+//! Record (48 bytes)
+//! | Offset | Type     | Field                                        |
+//! |--------|----------|----------------------------------------------|
+//! | 0      | [f64; 3] | Unit vector x, y, z                          |
+//! | 24     | f32      | Magnitude                                    |
+//! | 28     | u32      | Catalog quality flags (`Star::flags`)        |
+//! | 32     | u32      | Magnitude band: 0 V, 1 sensor                |
+//! | 36     | u32      | Id catalog: 0 none, 1 HR, 2 HIP, 3 TYC, 4 Gaia DR3, 5 HD |
+//! | 40     | u64      | Id number, TYC packed as tyc1 << 24 \| tyc2 << 8 \| tyc3 |
+//!
+//! Version 1 had 40 byte records holding only the HR number and version 2 an `f32` epoch,
+//! neither is read any more.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use nalgebra::Vector3;
+
+use super::error::SatSightError;
+use super::frames::{lat_lon_to_unit_vector, unit_vector_to_lat_lon, Frame};
+use super::photometry::MagnitudeBand;
+use super::{Star, StarId, StarVec};
+
+pub const BINARY_CATALOG_MAGIC: [u8; 8] = *b"SATSCAT\0";
+pub const BINARY_CATALOG_VERSION: u16 = 3;
+pub const BINARY_CATALOG_HEADER_SIZE: usize = 32;
+pub const BINARY_CATALOG_RECORD_SIZE: usize = 48;
+
+/// The decoded header of a binary catalog
+#[derive(Clone, Copy, Debug)]
+pub struct BinaryCatalogHeader {
+    pub version: u16,
+    pub frame: Frame,   // Frame the unit vectors are expressed in
+    pub count: u32,     // Number of records
+    pub epoch: f64,     // Julian epoch of the positions
+    pub checksum: u32,  // CRC-32 of the record bytes
+}
+
+/// A single star of a binary catalog
+#[derive(Clone, Copy, Debug)]
+pub struct CatalogRecord {
+    pub direction: Vector3<f64>, // Unit vector in the catalog frame
+    pub mag: f32,                // Magnitude in `band`
+    pub band: MagnitudeBand,     // Band of the magnitude
+    pub id: StarId,              // Catalog identifier of the star
+    pub flags: u32,              // Catalog quality flags, see `Star::flags`
+}
+
+impl CatalogRecord {
+    /// Converts the record into a `Star`, the catalog has to be in the galactic frame
+    pub fn to_star(&self) -> Result<Star, SatSightError> {
+        let (lat, lon) = unit_vector_to_lat_lon(&self.direction)?;
+        Ok(Star {
+            hr: match self.id {
+                StarId::Hr(hr) => hr,
+                _ => 0,
+            },
+            id: self.id,
+            lat,
+            lon,
+            mag: self.mag,
+            band: self.band,
+            fingure_print: 0.0,
+            flags: self.flags,
+        })
+    }
+}
+
+// ==== Checksum ===================================================================================
+// CRC-32 (IEEE 802.3, reflected 0xEDB88320), the same as zlib and png
+// =================================================================================================
+
+/// Calculates the CRC-32 of a byte slice
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn frame_code(frame: Frame) -> Result<u16, SatSightError> {
+    match frame {
+        Frame::Equatorial => Ok(0),
+        Frame::Galactic => Ok(1),
+        Frame::Ecliptic => Ok(2),
+        _ => Err(SatSightError::InvalidBinaryCatalog("only fixed frames can be stored".to_string())),
+    }
+}
+
+fn frame_from_code(code: u16) -> Result<Frame, SatSightError> {
+    match code {
+        0 => Ok(Frame::Equatorial),
+        1 => Ok(Frame::Galactic),
+        2 => Ok(Frame::Ecliptic),
+        _ => Err(SatSightError::InvalidBinaryCatalog(format!("unknown frame code {}", code))),
+    }
+}
+
+fn band_from_code(code: u32) -> Option<MagnitudeBand> {
+    [MagnitudeBand::V, MagnitudeBand::Sensor].into_iter().find(|band| band.code() == code)
+}
+
+fn id_code(id: StarId) -> (u32, u64) {
+    match id {
+        StarId::Unknown => (0, 0),
+        StarId::Hr(hr) => (1, hr as u64),
+        StarId::Hip(hip) => (2, hip as u64),
+        StarId::Tyc(tyc1, tyc2, tyc3) => (3, (tyc1 as u64) << 24 | (tyc2 as u64) << 8 | tyc3 as u64),
+        StarId::Gaia(source_id) => (4, source_id),
+        StarId::Hd(hd) => (5, hd as u64),
+    }
+}
+
+fn id_from_code(kind: u32, value: u64) -> Option<StarId> {
+    let number = u32::try_from(value).ok();
+    match kind {
+        0 if value == 0 => Some(StarId::Unknown),
+        1 => number.map(StarId::Hr),
+        2 => number.map(StarId::Hip),
+        3 if value >> 40 == 0 => Some(StarId::Tyc((value >> 24) as u16, (value >> 8) as u16, value as u8)),
+        4 => Some(StarId::Gaia(value)),
+        5 => number.map(StarId::Hd),
+        _ => None,
+    }
+}
+
+// ==== Writer =====================================================================================
+// Encodes catalogs into the binary format
+// =================================================================================================
+
+/// Writes records as a binary catalog, the record directions are normalised
+pub fn write_binary_catalog<W: Write>(writer: &mut W, records: &[CatalogRecord], frame: Frame, epoch: f64) -> Result<(), SatSightError> {
+    let count = u32::try_from(records.len())
+        .map_err(|_| SatSightError::InvalidBinaryCatalog("too many records".to_string()))?;
+
+    let mut body = Vec::with_capacity(records.len() * BINARY_CATALOG_RECORD_SIZE);
+    for record in records {
+        let norm = record.direction.norm();
+        if norm == 0.0 || !norm.is_finite() {
+            return Err(SatSightError::degenerate("record has no direction"));
+        }
+        let direction = record.direction / norm;
+        body.extend_from_slice(&direction.x.to_le_bytes());
+        body.extend_from_slice(&direction.y.to_le_bytes());
+        body.extend_from_slice(&direction.z.to_le_bytes());
+        body.extend_from_slice(&record.mag.to_le_bytes());
+        let (id_kind, id_value) = id_code(record.id);
+        body.extend_from_slice(&record.flags.to_le_bytes());
+        body.extend_from_slice(&record.band.code().to_le_bytes());
+        body.extend_from_slice(&id_kind.to_le_bytes());
+        body.extend_from_slice(&id_value.to_le_bytes());
+    }
+
+    let mut header = Vec::with_capacity(BINARY_CATALOG_HEADER_SIZE);
+    header.extend_from_slice(&BINARY_CATALOG_MAGIC);
+    header.extend_from_slice(&BINARY_CATALOG_VERSION.to_le_bytes());
+    header.extend_from_slice(&(BINARY_CATALOG_HEADER_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&(BINARY_CATALOG_RECORD_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&frame_code(frame)?.to_le_bytes());
+    header.extend_from_slice(&count.to_le_bytes());
+    header.extend_from_slice(&crc32(&body).to_le_bytes());
+    header.extend_from_slice(&epoch.to_le_bytes());
+
+    writer.write_all(&header)?;
+    writer.write_all(&body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes galactic `Star`s (e.g. from `parse_star_file`) as a binary catalog
+pub fn write_star_binary_catalog(file: File, stars: &[Star], epoch: f64) -> Result<(), SatSightError> {
+    let records: Vec<CatalogRecord> = stars
+        .iter()
+        .map(|star| CatalogRecord {
+            direction: lat_lon_to_unit_vector(star.lat, star.lon),
+            mag: star.mag,
+            band: star.band,
+            id: star.id,
+            flags: star.flags,
+        })
+        .collect();
+    write_binary_catalog(&mut BufWriter::new(file), &records, Frame::Galactic, epoch)
+}
+
+/// Writes star vectors (e.g. from `parse_star_vec_file`) as a binary catalog.
+/// Star vectors do not record their frame, so `frame` is the one their components are in.
+pub fn write_star_vec_binary_catalog(file: File, star_vecs: &[StarVec], frame: Frame, epoch: f64) -> Result<(), SatSightError> {
+    let records: Vec<CatalogRecord> = star_vecs
+        .iter()
+        .map(|star_vec| CatalogRecord {
+            direction: star_vec.to_vector(),
+            mag: star_vec.mag,
+            band: star_vec.band,
+            id: StarId::from_hr(star_vec.hr),
+            flags: 0,
+        })
+        .collect();
+    write_binary_catalog(&mut BufWriter::new(file), &records, frame, epoch)
+}
+
+// ==== Reader =====================================================================================
+// Zero-copy access to a binary catalog held in memory
+// =================================================================================================
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(buf)
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_bits(read_u32(bytes, offset))
+}
+
+fn read_f64(bytes: &[u8], offset: usize) -> f64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[offset..offset + 8]);
+    f64::from_le_bytes(buf)
+}
+
+/// A binary catalog read in place from a byte slice.
+///
+/// The slice can come from a memory map, `include_bytes!` or `std::fs::read`.
+/// Records are decoded on access, nothing is copied when the catalog is opened.
+#[derive(Clone, Copy, Debug)]
+pub struct BinaryCatalog<'a> {
+    header: BinaryCatalogHeader,
+    records: &'a [u8],
+}
+
+impl<'a> BinaryCatalog<'a> {
+    /// Validates the header, the sizes and the checksum of a binary catalog
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, SatSightError> {
+        let invalid = |message: &str| SatSightError::InvalidBinaryCatalog(message.to_string());
+
+        if bytes.len() < BINARY_CATALOG_HEADER_SIZE {
+            return Err(invalid("file is shorter than the header"));
+        }
+        if bytes[0..8] != BINARY_CATALOG_MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        let version = read_u16(bytes, 8);
+        if version != BINARY_CATALOG_VERSION {
+            return Err(SatSightError::InvalidBinaryCatalog(format!("unsupported version {}", version)));
+        }
+        let header_size = read_u16(bytes, 10) as usize;
+        let record_size = read_u16(bytes, 12) as usize;
+        if header_size != BINARY_CATALOG_HEADER_SIZE || record_size != BINARY_CATALOG_RECORD_SIZE {
+            return Err(invalid("unexpected header or record size"));
+        }
+
+        let header = BinaryCatalogHeader {
+            version,
+            frame: frame_from_code(read_u16(bytes, 14))?,
+            count: read_u32(bytes, 16),
+            checksum: read_u32(bytes, 20),
+            epoch: read_f64(bytes, 24),
+        };
+
+        let records = &bytes[header_size..];
+        if records.len() != header.count as usize * record_size {
+            return Err(invalid("record count does not match the file size"));
+        }
+        if crc32(records) != header.checksum {
+            return Err(invalid("checksum mismatch"));
+        }
+        if records.chunks_exact(record_size).any(|record| band_from_code(read_u32(record, 32)).is_none()) {
+            return Err(invalid("unknown magnitude band"));
+        }
+        if records.chunks_exact(record_size).any(|record| id_from_code(read_u32(record, 36), read_u64(record, 40)).is_none()) {
+            return Err(invalid("invalid star id"));
+        }
+
+        Ok(BinaryCatalog { header, records })
+    }
+
+    pub fn header(&self) -> &BinaryCatalogHeader {
+        &self.header
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.count as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.header.count == 0
+    }
+
+    /// Decodes the record at `index`
+    pub fn get(&self, index: usize) -> Option<CatalogRecord> {
+        if index >= self.len() {
+            return None;
+        }
+        let record = &self.records[index * BINARY_CATALOG_RECORD_SIZE..(index + 1) * BINARY_CATALOG_RECORD_SIZE];
+        Some(CatalogRecord {
+            direction: Vector3::new(read_f64(record, 0), read_f64(record, 8), read_f64(record, 16)),
+            mag: read_f32(record, 24),
+            band: band_from_code(read_u32(record, 32))?,
+            id: id_from_code(read_u32(record, 36), read_u64(record, 40))?,
+            flags: read_u32(record, 28),
+        })
+    }
+
+    /// Iterates over all records in file order
+    pub fn iter(&self) -> impl Iterator<Item = CatalogRecord> + 'a {
+        let catalog = *self;
+        (0..self.len()).filter_map(move |index| catalog.get(index))
+    }
+
+    /// Decodes every record into a galactic `Star`
+    pub fn to_stars(&self) -> Result<Vec<Star>, SatSightError> {
+        if self.header.frame != Frame::Galactic {
+            return Err(SatSightError::InvalidBinaryCatalog("stars need a galactic catalog".to_string()));
+        }
+        self.iter().map(|record| record.to_star()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<CatalogRecord> {
+        vec![
+            CatalogRecord {
+                direction: Vector3::new(1.0, 2.0, 2.0),
+                mag: 1.5,
+                band: MagnitudeBand::V,
+                id: StarId::Hr(2491),
+                flags: 2,
+            },
+            CatalogRecord {
+                direction: Vector3::new(0.0, 0.0, -1.0),
+                mag: 6.25,
+                band: MagnitudeBand::Sensor,
+                id: StarId::Tyc(9537, 12345, 2),
+                flags: 0,
+            },
+            CatalogRecord {
+                direction: Vector3::new(0.0, 1.0, 0.0),
+                mag: 11.0,
+                band: MagnitudeBand::V,
+                id: StarId::Gaia(5_853_498_713_190_525_696),
+                flags: 1,
+            },
+            CatalogRecord {
+                direction: Vector3::new(-1.0, 0.0, 0.0),
+                mag: 8.5,
+                band: MagnitudeBand::V,
+                id: StarId::Unknown,
+                flags: 0,
+            },
+        ]
+    }
+
+    fn encode(records: &[CatalogRecord]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_binary_catalog(&mut bytes, records, Frame::Equatorial, 2000.0).unwrap();
+        bytes
+    }
+
+    fn is_invalid(result: Result<BinaryCatalog, SatSightError>, expected: &str) -> bool {
+        matches!(result, Err(SatSightError::InvalidBinaryCatalog(message)) if message.contains(expected))
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let bytes = encode(&records());
+        assert_eq!(bytes.len(), BINARY_CATALOG_HEADER_SIZE + 4 * BINARY_CATALOG_RECORD_SIZE);
+
+        let catalog = BinaryCatalog::from_bytes(&bytes).unwrap();
+        assert_eq!((catalog.header().frame, catalog.header().epoch, catalog.len()), (Frame::Equatorial, 2000.0, 4));
+        for (read, written) in catalog.iter().zip(records()) {
+            assert!((read.direction - written.direction.normalize()).norm() < 1e-15);
+            assert_eq!((read.mag, read.band, read.id, read.flags), (written.mag, written.band, written.id, written.flags));
+        }
+        assert!(catalog.get(4).is_none());
+    }
+
+    #[test]
+    fn stars_keep_their_id_and_hr() {
+        let mut bytes = Vec::new();
+        write_binary_catalog(&mut bytes, &records(), Frame::Galactic, 2000.0).unwrap();
+        let stars = BinaryCatalog::from_bytes(&bytes).unwrap().to_stars().unwrap();
+        let ids: Vec<(u32, StarId)> = stars.iter().map(|star| (star.hr, star.id)).collect();
+        assert_eq!(ids[..2], [(2491, StarId::Hr(2491)), (0, StarId::Tyc(9537, 12345, 2))]);
+        assert_eq!(ids[2..], [(0, StarId::Gaia(5_853_498_713_190_525_696)), (0, StarId::Unknown)]);
+    }
+
+    #[test]
+    fn an_unknown_id_catalog_is_rejected() {
+        let mut bytes = encode(&records());
+        let record = BINARY_CATALOG_HEADER_SIZE + BINARY_CATALOG_RECORD_SIZE;
+        bytes[record + 36] = 9;
+        let checksum = crc32(&bytes[BINARY_CATALOG_HEADER_SIZE..]);
+        bytes[20..24].copy_from_slice(&checksum.to_le_bytes());
+        assert!(is_invalid(BinaryCatalog::from_bytes(&bytes), "invalid star id"));
+    }
+
+    #[test]
+    fn the_epoch_keeps_double_precision() {
+        // 2031.734 is a day of a mission, as an f32 it would be off by about four minutes
+        let mut bytes = Vec::new();
+        write_binary_catalog(&mut bytes, &records(), Frame::Equatorial, 2031.734123456).unwrap();
+        assert_eq!(BinaryCatalog::from_bytes(&bytes).unwrap().header().epoch, 2031.734123456);
+    }
+
+    #[test]
+    fn a_corrupted_byte_fails_the_checksum() {
+        let mut bytes = encode(&records());
+        bytes[BINARY_CATALOG_HEADER_SIZE + 5] ^= 0x10;
+        assert!(is_invalid(BinaryCatalog::from_bytes(&bytes), "checksum"));
+    }
+
+    #[test]
+    fn a_truncated_file_is_rejected() {
+        let bytes = encode(&records());
+        assert!(is_invalid(BinaryCatalog::from_bytes(&bytes[..bytes.len() - 1]), "record count"));
+        assert!(is_invalid(BinaryCatalog::from_bytes(&bytes[..BINARY_CATALOG_HEADER_SIZE - 1]), "shorter than the header"));
+    }
+}
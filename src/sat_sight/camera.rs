@@ -0,0 +1,424 @@
+//! Camera model.
+//!
+//! Replaces the scattered image size, field of view and pixel scale
+//! constants with one description of the camera, so the forward projection
+//! (direction to pixel) and the inverse projection (pixel to direction)
+//! always agree. Sensors do not have to be square and the boresight does not
+//! have to hit the centre of the image.
+//!
+//! Directions are in the camera frame: +z along the boresight, +x along the
+//! image columns and +y along the image rows. At zero roll +x is east and +y
+//! is north (see `attitude`), so east is to the right and north is down the
+//! rows, the same orientation as the azimuthal projections of `sat_sight`. Pixel coordinates are
+//! continuous with (0, 0) at the top left corner of the image, so pixel
+//! (i, j) covers [i, i + 1) x [j, j + 1).
+//!
+//! The lens maps the angle from the boresight to a distance from the
+//! principal point with one of the azimuthal projections in `LensProjection`,
+//! a pinhole being the gnomonic one. Lens distortion is applied on top and
+//! follows the Brown-Conrady model with the same coefficients as OpenCV, so
+//! calibrations can be copied over directly.
+
+use nalgebra::{Matrix3, Vector3};
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use super::error::SatSightError;
+
+/// Undistorting stops once a step moves the point less than this (normalised image units)
+const UNDISTORT_TOLERANCE: f64 = 1e-12;
+const UNDISTORT_MAX_ITERATIONS: usize = 20;
+
+/// How a lens maps the angle from the boresight to a distance from the principal point,
+/// measured in focal lengths
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LensProjection {
+    #[default]
+    Gnomonic,      // Pinhole, r = tan(theta), less than 90 degrees off the boresight
+    Orthographic,  // r = sin(theta), up to 90 degrees
+    Stereographic, // r = 2 tan(theta / 2), less than 180 degrees
+    Equidistant,   // Fisheye, r = theta, less than 180 degrees
+}
+
+impl LensProjection {
+    /// Distance from the principal point (focal lengths) of a direction `theta` (rad) off the
+    /// boresight, `None` when the lens can not see that far out
+    pub fn radius(&self, theta: f64) -> Option<f64> {
+        match self {
+            LensProjection::Gnomonic if theta < FRAC_PI_2 => Some(theta.tan()),
+            LensProjection::Orthographic if theta <= FRAC_PI_2 => Some(theta.sin()),
+            LensProjection::Stereographic if theta < PI => Some(2.0 * (theta / 2.0).tan()),
+            LensProjection::Equidistant if theta < PI => Some(theta),
+            _ => None,
+        }
+    }
+
+    /// Angle off the boresight (rad) seen at a distance from the principal point (focal
+    /// lengths). Distances past the rim of an orthographic or equidistant image map to the rim.
+    pub fn angle(&self, radius: f64) -> f64 {
+        match self {
+            LensProjection::Gnomonic => radius.atan(),
+            LensProjection::Orthographic => radius.min(1.0).asin(),
+            LensProjection::Stereographic => 2.0 * (radius / 2.0).atan(),
+            LensProjection::Equidistant => radius.min(PI),
+        }
+    }
+}
+
+/// Brown-Conrady radial and tangential distortion, applied to normalised image coordinates
+/// (x / z, y / z). All zero is an ideal pinhole lens.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Distortion {
+    pub k1: f64, // Radial coefficients of r^2, r^4 and r^6, negative for barrel distortion
+    pub k2: f64,
+    pub k3: f64,
+    pub p1: f64, // Tangential (decentring) coefficients
+    pub p2: f64,
+}
+
+impl Distortion {
+    pub fn is_none(&self) -> bool {
+        *self == Distortion::default()
+    }
+
+    /// True while the radial part of the distortion still grows with the radius all the way out
+    /// to `radius` (normalised image units). Past the first turning point the image folds back
+    /// on itself, so directions further out would land on pixels that belong to closer ones.
+    pub fn is_monotonic_to(&self, radius: f64) -> bool {
+        // Derivative of r (1 + k1 r^2 + k2 r^4 + k3 r^6) as a polynomial in u = r^2
+        let (a, b, c) = (3.0 * self.k1, 5.0 * self.k2, 7.0 * self.k3);
+        let slope = |u: f64| 1.0 + u * (a + u * (b + u * c));
+        let end = radius * radius;
+        let end_slope = slope(end);
+        if end_slope.is_nan() || end_slope <= 0.0 {
+            return false;
+        }
+
+        // The slope starts at 1, so it stays positive when it is positive at the end and at its
+        // turning points in between
+        let turning_points = if c != 0.0 {
+            let discriminant = b * b - 3.0 * a * c;
+            if discriminant < 0.0 {
+                [None, None]
+            } else {
+                let root = discriminant.sqrt();
+                [Some((-b + root) / (3.0 * c)), Some((-b - root) / (3.0 * c))]
+            }
+        } else if b != 0.0 {
+            [Some(-a / (2.0 * b)), None]
+        } else {
+            [None, None]
+        };
+        turning_points
+            .into_iter()
+            .flatten()
+            .filter(|u| *u > 0.0 && *u < end)
+            .all(|u| slope(u) > 0.0)
+    }
+
+    /// Moves an ideal normalised image point to where the lens puts it
+    pub fn distort(&self, point: (f64, f64)) -> (f64, f64) {
+        let (x, y) = point;
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+
+    /// Finds the ideal normalised image point the lens moved to `point`.
+    ///
+    /// Newton's method on `distort`, starting from the distorted point. Converges in a few
+    /// iterations for any lens whose distortion does not fold the image over itself; if it
+    /// does not converge the best estimate is returned.
+    pub fn undistort(&self, point: (f64, f64)) -> (f64, f64) {
+        if self.is_none() {
+            return point;
+        }
+        let (mut x, mut y) = point;
+        for _ in 0..UNDISTORT_MAX_ITERATIONS {
+            let (xd, yd) = self.distort((x, y));
+            let (ex, ey) = (xd - point.0, yd - point.1);
+
+            let r2 = x * x + y * y;
+            let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+            let d_radial = self.k1 + r2 * (2.0 * self.k2 + 3.0 * self.k3 * r2);
+            let j00 = radial + 2.0 * x * x * d_radial + 2.0 * self.p1 * y + 6.0 * self.p2 * x;
+            let j01 = 2.0 * x * y * d_radial + 2.0 * self.p1 * x + 2.0 * self.p2 * y;
+            let j11 = radial + 2.0 * y * y * d_radial + 6.0 * self.p1 * y + 2.0 * self.p2 * x;
+            let determinant = j00 * j11 - j01 * j01;
+            if determinant.abs() < f64::EPSILON {
+                break;
+            }
+
+            let dx = (j11 * ex - j01 * ey) / determinant;
+            let dy = (j00 * ey - j01 * ex) / determinant;
+            x -= dx;
+            y -= dy;
+            if dx.hypot(dy) < UNDISTORT_TOLERANCE {
+                break;
+            }
+        }
+        (x, y)
+    }
+}
+
+/// Intrinsic parameters of a camera
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraModel {
+    pub width: u32,                  // Image width (pixels)
+    pub height: u32,                 // Image height (pixels)
+    pub pixel_pitch: f64,            // Size of a square pixel (mm)
+    pub focal_length: f64,           // Focal length (mm)
+    pub principal_point: (f64, f64), // Where the boresight meets the image, (column, row) in pixels
+    pub skew: f64,                   // Skew between the pixel axes, the K[0][1] term (pixels)
+    pub projection: LensProjection,  // How the lens maps angles onto the image
+    pub distortion: Distortion,      // Lens distortion, none for an ideal lens
+}
+
+impl Default for CameraModel {
+    /// 720 x 720 pixels of 5.5 um with a 15 degree field of view, the camera the renders
+    /// in `main` used to assume
+    fn default() -> Self {
+        let pixel_pitch = 0.0055;
+        let focal_length_pixels = 360.0 / 7.5_f64.to_radians().tan();
+        CameraModel {
+            width: 720,
+            height: 720,
+            pixel_pitch,
+            focal_length: focal_length_pixels * pixel_pitch,
+            principal_point: (360.0, 360.0),
+            skew: 0.0,
+            projection: LensProjection::Gnomonic,
+            distortion: Distortion::default(),
+        }
+    }
+}
+
+impl CameraModel {
+    /// Pinhole camera with the principal point in the centre of the image, no skew and no
+    /// distortion
+    pub fn new(width: u32, height: u32, pixel_pitch: f64, focal_length: f64) -> Result<Self, SatSightError> {
+        if width == 0 || height == 0 {
+            return Err(SatSightError::degenerate("image has no pixels"));
+        }
+        let positive = |value: f64| value > 0.0 && value.is_finite();
+        if !positive(pixel_pitch) || !positive(focal_length) {
+            return Err(SatSightError::degenerate("pixel pitch and focal length have to be positive"));
+        }
+        Ok(CameraModel {
+            width,
+            height,
+            pixel_pitch,
+            focal_length,
+            principal_point: (width as f64 / 2.0, height as f64 / 2.0),
+            skew: 0.0,
+            projection: LensProjection::Gnomonic,
+            distortion: Distortion::default(),
+        })
+    }
+
+    /// Pinhole camera whose image width spans `horizontal_fov` degrees, the focal length
+    /// follows from it
+    pub fn from_fov(width: u32, height: u32, horizontal_fov: f64, pixel_pitch: f64) -> Result<Self, SatSightError> {
+        CameraModel::from_fov_with_projection(width, height, horizontal_fov, pixel_pitch, LensProjection::Gnomonic)
+    }
+
+    /// Camera with a `projection` lens whose image width spans `horizontal_fov` degrees
+    pub fn from_fov_with_projection(
+        width: u32,
+        height: u32,
+        horizontal_fov: f64,
+        pixel_pitch: f64,
+        projection: LensProjection,
+    ) -> Result<Self, SatSightError> {
+        let radius = match projection.radius((horizontal_fov / 2.0).to_radians()) {
+            Some(radius) if horizontal_fov > 0.0 => radius,
+            _ => return Err(SatSightError::degenerate("field of view is wider than the lens can see")),
+        };
+        let focal_length_pixels = width as f64 / 2.0 / radius;
+        let mut camera = CameraModel::new(width, height, pixel_pitch, focal_length_pixels * pixel_pitch)?;
+        camera.projection = projection;
+        Ok(camera)
+    }
+
+    /// Focal length expressed in pixels
+    pub fn focal_length_pixels(&self) -> f64 {
+        self.focal_length / self.pixel_pitch
+    }
+
+    /// The intrinsic matrix K, taking camera directions to homogeneous pixel coordinates.
+    /// It only describes a pinhole and does not include the lens distortion.
+    pub fn intrinsic_matrix(&self) -> Matrix3<f64> {
+        let f = self.focal_length_pixels();
+        let (cx, cy) = self.principal_point;
+        Matrix3::new(
+            f, self.skew, cx,
+            0.0, f, cy,
+            0.0, 0.0, 1.0,
+        )
+    }
+
+    /// Ideal image plane position (focal lengths) of a camera frame direction
+    fn to_image_plane(&self, direction: &Vector3<f64>) -> Option<(f64, f64)> {
+        if self.projection == LensProjection::Gnomonic {
+            return (direction.z > 0.0).then(|| (direction.x / direction.z, direction.y / direction.z));
+        }
+        let off_axis = direction.x.hypot(direction.y);
+        if off_axis == 0.0 {
+            return (direction.z > 0.0).then_some((0.0, 0.0));
+        }
+        let scale = self.projection.radius(off_axis.atan2(direction.z))? / off_axis;
+        Some((direction.x * scale, direction.y * scale))
+    }
+
+    /// Projects a camera frame direction (any length) through the lens onto the image plane and
+    /// returns the sub-pixel (column, row). Directions the lens can not see, e.g. at or behind
+    /// the image plane of a pinhole, have no projection, and neither have directions beyond the
+    /// radius where the distortion folds the image back, see `Distortion::is_monotonic_to`. The point is not checked against the
+    /// image bounds, see `contains`.
+    pub fn project(&self, direction: &Vector3<f64>) -> Option<(f64, f64)> {
+        let f = self.focal_length_pixels();
+        let (x, y) = self.to_image_plane(direction)?;
+        if !self.distortion.is_none() && !self.distortion.is_monotonic_to(x.hypot(y)) {
+            return None;
+        }
+        let (x, y) = self.distortion.distort((x, y));
+        Some((f * x + self.skew * y + self.principal_point.0, f * y + self.principal_point.1))
+    }
+
+    /// Returns the unit camera frame direction seen at a sub-pixel (column, row), e.g. a star
+    /// centroid. Inverts `project` including the lens distortion.
+    pub fn unproject(&self, pixel: (f64, f64)) -> Vector3<f64> {
+        let f = self.focal_length_pixels();
+        let y = (pixel.1 - self.principal_point.1) / f;
+        let x = (pixel.0 - self.principal_point.0 - self.skew * y) / f;
+        let (x, y) = self.distortion.undistort((x, y));
+        if self.projection == LensProjection::Gnomonic {
+            return Vector3::new(x, y, 1.0).normalize();
+        }
+        let radius = x.hypot(y);
+        if radius == 0.0 {
+            return Vector3::z();
+        }
+        let theta = self.projection.angle(radius);
+        let scale = theta.sin() / radius;
+        Vector3::new(x * scale, y * scale, theta.cos())
+    }
+
+    /// True when a sub-pixel position lies on the image
+    pub fn contains(&self, pixel: (f64, f64)) -> bool {
+        self.contains_with_margin(pixel, 0.0)
+    }
+
+    /// True when a sub-pixel position lies on the image widened by `margin` pixels on every
+    /// side, a negative margin shrinks it
+    pub fn contains_with_margin(&self, pixel: (f64, f64), margin: f64) -> bool {
+        (-margin..self.width as f64 + margin).contains(&pixel.0)
+            && (-margin..self.height as f64 + margin).contains(&pixel.1)
+    }
+
+    /// Angle spanned by the image width through the centre of the image (deg)
+    pub fn horizontal_fov(&self) -> f64 {
+        let row = self.height as f64 / 2.0;
+        self.angle_between_pixels((0.0, row), (self.width as f64, row))
+    }
+
+    /// Angle spanned by the image height through the centre of the image (deg)
+    pub fn vertical_fov(&self) -> f64 {
+        let column = self.width as f64 / 2.0;
+        self.angle_between_pixels((column, 0.0), (column, self.height as f64))
+    }
+
+    /// Angle spanned by the image diagonal, the widest angle the camera sees (deg)
+    pub fn diagonal_fov(&self) -> f64 {
+        self.angle_between_pixels((0.0, 0.0), (self.width as f64, self.height as f64))
+    }
+
+    /// Largest angle from the boresight seen on the image widened by `margin` pixels (deg).
+    /// The lens maps larger angles further from the principal point, so it is seen at a corner.
+    pub fn field_radius(&self, margin: f64) -> f64 {
+        let (left, top) = (-margin, -margin);
+        let (right, bottom) = (self.width as f64 + margin, self.height as f64 + margin);
+        [(left, top), (right, top), (left, bottom), (right, bottom)]
+            .into_iter()
+            .map(|corner| {
+                let direction = self.unproject(corner);
+                direction.xy().norm().atan2(direction.z).to_degrees()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// Angle between the directions seen at two sub-pixel positions, including distortion (deg)
+    pub fn angle_between_pixels(&self, a: (f64, f64), b: (f64, f64)) -> f64 {
+        let (a, b) = (self.unproject(a), self.unproject(b));
+        a.cross(&b).norm().atan2(a.dot(&b)).to_degrees()
+    }
+
+    /// Angle covered by one pixel at the principal point (deg)
+    pub fn pixel_scale(&self) -> f64 {
+        (1.0 / self.focal_length_pixels()).atan().to_degrees()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn barrel_distortion_does_not_fold_stars_back_into_the_frame() {
+        let mut camera = CameraModel::default();
+        camera.distortion.k1 = -0.3;
+        let principal_point = camera.principal_point;
+
+        let mut last_radius = 0.0;
+        let mut rejected = false;
+        for step in 0..=1790 {
+            let angle = (step as f64 * 0.05).to_radians();
+            let direction = Vector3::new(angle.sin(), 0.0, angle.cos());
+            match camera.project(&direction) {
+                Some(pixel) => {
+                    assert!(!rejected, "{} deg projects after a closer direction was rejected", step as f64 * 0.05);
+                    let radius = (pixel.0 - principal_point.0).hypot(pixel.1 - principal_point.1);
+                    assert!(radius >= last_radius, "radius shrinks at {} deg", step as f64 * 0.05);
+                    last_radius = radius;
+                }
+                None => rejected = true,
+            }
+        }
+        assert!(rejected);
+
+        // The stars that used to come back as ghosts inside the frame
+        for angle in [61.0_f64, 62.0] {
+            let angle = angle.to_radians();
+            assert!(camera.project(&Vector3::new(angle.sin(), 0.0, angle.cos())).is_none());
+        }
+    }
+
+    #[test]
+    fn monotonic_radius_matches_the_turning_point() {
+        // d/dr r (1 + k1 r^2) = 1 + 3 k1 r^2 turns at r = 1 / sqrt(-3 k1)
+        let distortion = Distortion { k1: -0.3, ..Distortion::default() };
+        let turning_point = 1.0 / 0.9_f64.sqrt();
+        assert!(distortion.is_monotonic_to(turning_point - 1e-9));
+        assert!(!distortion.is_monotonic_to(turning_point + 1e-9));
+
+        // A positive k2 brings the slope back up, the first turning point still counts
+        let distortion = Distortion { k1: -0.5, k2: 0.1, ..Distortion::default() };
+        assert!(!distortion.is_monotonic_to(10.0));
+        assert!(Distortion::default().is_monotonic_to(1e6));
+    }
+
+    #[test]
+    fn project_and_unproject_agree_with_distortion() {
+        let mut camera = CameraModel::from_fov(1280, 960, 60.0, 0.003).unwrap();
+        camera.distortion = Distortion { k1: -0.25, k2: 0.05, k3: -0.01, p1: 0.001, p2: -0.0005 };
+        for column in 0..=32 {
+            for row in 0..=24 {
+                let pixel = (column as f64 * 40.0, row as f64 * 40.0);
+                let back = camera.project(&camera.unproject(pixel)).unwrap();
+                assert!((back.0 - pixel.0).hypot(back.1 - pixel.1) < 1e-9, "{:?} came back as {:?}", pixel, back);
+            }
+        }
+    }
+}
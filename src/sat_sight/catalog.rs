@@ -0,0 +1,536 @@
+//! Readers for the raw star catalogs in `data/raw`.
+//!
+//! The Bright Star Catalogue reader works directly on the fixed-width
+//! `bsc5.dat` file so the `data/formated` csv files no longer have to be
+//! regenerated by hand before a catalog can be loaded. Csv catalogs are
+//! read by header name through a `CatalogSchema`, so column order does not matter.
+
+use csv::{Reader, StringRecord};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+use super::error::SatSightError;
+use super::photometry::{MagnitudeBand, SensorModel};
+use super::frames::equatorial_to_galactic;
+use super::{Star, StarId, FLAG_HIGH_PROPER_MOTION, FLAG_MISSING_PHOTOMETRY, FLAG_MULTIPLE, FLAG_VARIABLE, HIGH_PROPER_MOTION};
+
+/// A single entry of the Yale Bright Star Catalogue, 5th revised edition.
+///
+/// Only the fields that are useful for star tracking are kept. Positions are
+/// J2000.0 and given in degrees, proper motions in arcseconds per year.
+#[derive(Clone, Debug)]
+pub struct BscStar {
+    pub hr: u32,                   // Harvard Revised Number
+    pub name: String,              // Bayer and/or Flamsteed name
+    pub hd: Option<u32>,           // Henry Draper Catalog Number
+    pub multiple: Option<char>,    // Double or multiple-star code [AWDIRS]
+    pub var_id: String,            // Variable star identification
+    pub ra: f64,                   // Right ascension J2000 (deg)
+    pub dec: f64,                  // Declination J2000 (deg)
+    pub glon: f64,                 // Galactic longitude (deg)
+    pub glat: f64,                 // Galactic latitude (deg)
+    pub vmag: f32,                 // Visual magnitude
+    pub b_v: Option<f32>,          // B-V color in the UBV system
+    pub sp_type: String,           // Spectral type
+    pub pm_ra: f64,                // Proper motion in RA, cos(dec) * d(ra)/dt (arcsec/yr)
+    pub pm_dec: f64,               // Proper motion in Dec (arcsec/yr)
+    pub parallax: Option<f32>,     // Parallax (arcsec)
+    pub dynamical_parallax: bool,  // Parallax is dynamical rather than trigonometric
+    pub dmag: Option<f32>,         // Magnitude difference of double or brightest multiple
+    pub sep: Option<f32>,          // Separation of components (arcsec)
+    pub mult_id: String,           // Identifications of components in Dmag
+    pub mult_cnt: Option<u8>,      // Number of components assigned to a multiple
+}
+
+impl BscStar {
+    /// Catalog quality flags of the entry, see `FLAG_*` in `sat_sight`
+    pub fn flags(&self) -> u32 {
+        quality_flags(
+            !self.var_id.is_empty(),
+            self.multiple.is_some() || self.mult_cnt.is_some() || self.dmag.is_some(),
+            Some((self.pm_ra, self.pm_dec)),
+            self.b_v.is_none(),
+        )
+    }
+
+    /// Converts the catalog entry into the galactic `Star` used by the rest of `sat_sight`
+    pub fn to_star(&self) -> Star {
+        Star {
+            hr: self.hr,
+            id: StarId::Hr(self.hr),
+            lat: self.glat,
+            lon: self.glon,
+            mag: self.vmag,
+            band: MagnitudeBand::V,
+            fingure_print: 0.0,
+            flags: self.flags(),
+        }
+    }
+
+    /// Same as `to_star` with the magnitude converted into the sensor's band
+    pub fn to_sensor_star(&self, model: &SensorModel) -> Star {
+        Star {
+            mag: model.sensor_magnitude(self.vmag, self.b_v, &self.sp_type),
+            band: MagnitudeBand::Sensor,
+            ..self.to_star()
+        }
+    }
+}
+
+/// Combines the catalog quality information of a star into `Star::flags`
+pub(crate) fn quality_flags(variable: bool, multiple: bool, proper_motion: Option<(f64, f64)>, missing_photometry: bool) -> u32 {
+    let mut flags = 0;
+    if variable {
+        flags |= FLAG_VARIABLE;
+    }
+    if multiple {
+        flags |= FLAG_MULTIPLE;
+    }
+    if let Some((pm_ra, pm_dec)) = proper_motion {
+        if pm_ra.hypot(pm_dec) > HIGH_PROPER_MOTION {
+            flags |= FLAG_HIGH_PROPER_MOTION;
+        }
+    }
+    if missing_photometry {
+        flags |= FLAG_MISSING_PHOTOMETRY;
+    }
+    flags
+}
+
+// ==== Bright Star Catalogue ======================================================================
+// Byte layout from data/raw/bsc5.readme, bytes are 1 based and inclusive
+// =================================================================================================
+
+/// Returns the trimmed text between the 1 based inclusive `start` and `end` bytes of a record.
+/// Records in `bsc5.dat` have their trailing blanks stripped so short lines give empty fields.
+pub(crate) fn bsc_field(line: &str, start: usize, end: usize) -> &str {
+    let end = end.min(line.len());
+    if start > end {
+        return "";
+    }
+    line.get(start - 1..end).unwrap_or("").trim()
+}
+
+/// Parses an optional numeric field, blank fields are `None`
+pub(crate) fn parse_optional<T: FromStr>(line: &str, start: usize, end: usize, label: &str, line_number: u64) -> Result<Option<T>, SatSightError> {
+    let text = bsc_field(line, start, end);
+    if text.is_empty() {
+        return Ok(None);
+    }
+    text.parse()
+        .map(Some)
+        .map_err(|_| SatSightError::parse(line_number, label, format!("invalid value '{}' in bytes {}-{}", text, start, end)))
+}
+
+/// Parses a numeric field that has to be present for a catalog star
+pub(crate) fn parse_required<T: FromStr>(line: &str, start: usize, end: usize, label: &str, line_number: u64) -> Result<T, SatSightError> {
+    parse_optional(line, start, end, label, line_number)?
+        .ok_or_else(|| SatSightError::parse(line_number, label, format!("missing value in bytes {}-{}", start, end)))
+}
+
+/// Parses one `bsc5.dat` record.
+/// Returns `None` for the nova and extragalactic placeholder records, those only keep their
+/// HR number and name and have all of their position and photometry fields blank.
+pub fn parse_bsc5_record(line: &str, line_number: u64) -> Result<Option<BscStar>, SatSightError> {
+    let hr = parse_required(line, 1, 4, "HR", line_number)?;
+
+    // Placeholder entries have no J2000 position (note 1 of the readme)
+    if bsc_field(line, 76, 90).is_empty() {
+        return Ok(None);
+    }
+
+    let ra_h: f64 = parse_required(line, 76, 77, "RAh", line_number)?;
+    let ra_m: f64 = parse_required(line, 78, 79, "RAm", line_number)?;
+    let ra_s: f64 = parse_required(line, 80, 83, "RAs", line_number)?;
+    let dec_sign = if bsc_field(line, 84, 84) == "-" { -1.0 } else { 1.0 };
+    let dec_d: f64 = parse_required(line, 85, 86, "DEd", line_number)?;
+    let dec_m: f64 = parse_required(line, 87, 88, "DEm", line_number)?;
+    let dec_s: f64 = parse_required(line, 89, 90, "DEs", line_number)?;
+
+    Ok(Some(BscStar {
+        hr,
+        name: bsc_field(line, 5, 14).to_string(),
+        hd: parse_optional(line, 26, 31, "HD", line_number)?,
+        multiple: bsc_field(line, 44, 44).chars().next(),
+        var_id: bsc_field(line, 52, 60).to_string(),
+        ra: (ra_h + ra_m / 60.0 + ra_s / 3600.0) * 15.0,
+        dec: dec_sign * (dec_d + dec_m / 60.0 + dec_s / 3600.0),
+        glon: parse_required(line, 91, 96, "GLON", line_number)?,
+        glat: parse_required(line, 97, 102, "GLAT", line_number)?,
+        vmag: parse_required(line, 103, 107, "Vmag", line_number)?,
+        b_v: parse_optional(line, 110, 114, "B-V", line_number)?,
+        sp_type: bsc_field(line, 128, 147).to_string(),
+        pm_ra: parse_optional(line, 149, 154, "pmRA", line_number)?.unwrap_or(0.0),
+        pm_dec: parse_optional(line, 155, 160, "pmDE", line_number)?.unwrap_or(0.0),
+        parallax: parse_optional(line, 162, 166, "Parallax", line_number)?,
+        dynamical_parallax: bsc_field(line, 161, 161) == "D",
+        dmag: parse_optional(line, 181, 184, "Dmag", line_number)?,
+        sep: parse_optional(line, 185, 190, "Sep", line_number)?,
+        mult_id: bsc_field(line, 191, 194).to_string(),
+        mult_cnt: parse_optional(line, 195, 196, "MultCnt", line_number)?,
+    }))
+}
+
+/// Parses the fixed-width `bsc5.dat` file and returns every star in the catalog.
+/// The 14 nova and extragalactic placeholder records are skipped.
+pub fn parse_bsc5_file(file: File) -> Result<Vec<BscStar>, SatSightError> {
+    let reader = BufReader::new(file);
+
+    let mut stars: Vec<BscStar> = Vec::new();
+    for (line_number, line) in (1..).zip(reader.lines()) {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(star) = parse_bsc5_record(&line, line_number)? {
+            stars.push(star);
+        }
+    }
+    Ok(stars)
+}
+
+/// Parses `bsc5.dat` and converts the entries into galactic `Star`s
+pub fn parse_bsc5_star_file(file: File) -> Result<Vec<Star>, SatSightError> {
+    Ok(parse_bsc5_file(file)?.iter().map(BscStar::to_star).collect())
+}
+
+// ==== Csv Catalogs ===============================================================================
+// Header driven loading of csv star catalogs
+// =================================================================================================
+
+/// Header names of the csv columns that make up a `Star`.
+///
+/// Positions are read from the galactic `glon`/`glat` columns. When a file has no galactic
+/// columns the equatorial J2000 `ra`/`dec` columns (degrees) are used and converted.
+/// Header names are matched case insensitively. The quality columns (`var_id` to `b_v`)
+/// are optional and fill in `Star::flags` when the file has them, the same way
+/// `BscStar::flags` does for `bsc5.dat`. `b_v` and `sp_type` also give the colour
+/// `parse_sensor_star_file_with_schema` converts the magnitudes with.
+#[derive(Clone, Debug)]
+pub struct CatalogSchema {
+    pub hr: String,
+    pub glon: String,
+    pub glat: String,
+    pub ra: String,
+    pub dec: String,
+    pub mag: String,
+    pub var_id: String,
+    pub multiple: String,
+    pub mult_cnt: String,
+    pub dmag: String,
+    pub pm_ra: String,
+    pub pm_dec: String,
+    pub b_v: String,
+    pub sp_type: String,
+}
+
+impl Default for CatalogSchema {
+    /// Matches the `data/formated` csv files and the `yale_bright_star_catalog_v5.csv` export
+    fn default() -> Self {
+        CatalogSchema {
+            hr: "HR".to_string(),
+            glon: "GLON".to_string(),
+            glat: "GLAT".to_string(),
+            ra: "RA".to_string(),
+            dec: "Dec".to_string(),
+            mag: "Vmag".to_string(),
+            var_id: "VarID".to_string(),
+            multiple: "Multiple".to_string(),
+            mult_cnt: "MultCnt".to_string(),
+            dmag: "Dmag".to_string(),
+            pm_ra: "pmRA".to_string(),
+            pm_dec: "pmDE".to_string(),
+            b_v: "B-V".to_string(),
+            sp_type: "SpType".to_string(),
+        }
+    }
+}
+
+impl CatalogSchema {
+    /// Parses a schema description of `field=header` pairs, e.g. `hr=HIP,ra=RAdeg,dec=DEdeg,mag=Vmag`.
+    /// Fields that are not listed keep their default header name.
+    pub fn parse(description: &str) -> Result<Self, SatSightError> {
+        let mut schema = CatalogSchema::default();
+        for pair in description.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (field, header) = pair
+                .split_once('=')
+                .ok_or_else(|| SatSightError::InvalidSchema(format!("entry '{}' is not of the form field=header", pair)))?;
+            let header = header.trim().to_string();
+            match field.trim().to_ascii_lowercase().as_str() {
+                "hr" => schema.hr = header,
+                "glon" => schema.glon = header,
+                "glat" => schema.glat = header,
+                "ra" => schema.ra = header,
+                "dec" => schema.dec = header,
+                "mag" | "vmag" => schema.mag = header,
+                "var_id" => schema.var_id = header,
+                "multiple" => schema.multiple = header,
+                "mult_cnt" => schema.mult_cnt = header,
+                "dmag" => schema.dmag = header,
+                "pm_ra" => schema.pm_ra = header,
+                "pm_dec" => schema.pm_dec = header,
+                "b_v" => schema.b_v = header,
+                "sp_type" => schema.sp_type = header,
+                other => return Err(SatSightError::InvalidSchema(format!("unknown field '{}'", other))),
+            }
+        }
+        Ok(schema)
+    }
+}
+
+/// Where the position of a star comes from in a csv file
+enum PositionColumns {
+    Galactic { glon: usize, glat: usize },
+    Equatorial { ra: usize, dec: usize },
+}
+
+/// Finds the index of a header, ignoring case and surrounding whitespace
+pub(crate) fn find_column(headers: &StringRecord, name: &str) -> Option<usize> {
+    headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name.trim()))
+}
+
+/// Same as `find_column` but a missing header is an error
+pub(crate) fn require_column(headers: &StringRecord, name: &str) -> Result<usize, SatSightError> {
+    find_column(headers, name).ok_or_else(|| SatSightError::parse(1, name, "header not found"))
+}
+
+/// Parses a csv field, blank fields are `None`
+pub(crate) fn parse_csv_field<T: FromStr>(record: &StringRecord, column: usize, name: &str) -> Result<Option<T>, SatSightError> {
+    let text = record.get(column).unwrap_or("").trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let line = record.position().map_or(0, |position| position.line());
+    text.parse()
+        .map(Some)
+        .map_err(|_| SatSightError::parse(line, name, format!("invalid value '{}'", text)))
+}
+
+/// Returns true when the optional column exists and the field is not blank
+fn has_value(record: &StringRecord, column: Option<usize>) -> bool {
+    column.and_then(|column| record.get(column)).is_some_and(|text| !text.trim().is_empty())
+}
+
+/// Parses a csv star catalog, finding the columns by their header names.
+/// Rows without a position, like the nova placeholders in the full Yale export, are skipped.
+/// Magnitudes are kept in the V band of the file.
+pub fn parse_star_file_with_schema(file: File, schema: &CatalogSchema) -> Result<Vec<Star>, SatSightError> {
+    parse_csv_catalog(file, schema, None)
+}
+
+/// Same as `parse_star_file_with_schema` with the magnitudes converted into the sensor's band
+/// from the `b_v` colour, or the `sp_type` spectral type when the colour is blank, the same
+/// conversion as `BscStar::to_sensor_star`
+pub fn parse_sensor_star_file_with_schema(file: File, schema: &CatalogSchema, model: &SensorModel) -> Result<Vec<Star>, SatSightError> {
+    parse_csv_catalog(file, schema, Some(model))
+}
+
+fn parse_csv_catalog<R: Read>(input: R, schema: &CatalogSchema, model: Option<&SensorModel>) -> Result<Vec<Star>, SatSightError> {
+    let mut reader = Reader::from_reader(input);
+    let headers = reader.headers()?.clone();
+
+    let hr_column = require_column(&headers, &schema.hr)?;
+    let mag_column = require_column(&headers, &schema.mag)?;
+    let position = match (find_column(&headers, &schema.glon), find_column(&headers, &schema.glat)) {
+        (Some(glon), Some(glat)) => PositionColumns::Galactic { glon, glat },
+        _ => PositionColumns::Equatorial {
+            ra: require_column(&headers, &schema.ra)?,
+            dec: require_column(&headers, &schema.dec)?,
+        },
+    };
+    let var_id_column = find_column(&headers, &schema.var_id);
+    let multiple_column = find_column(&headers, &schema.multiple);
+    let mult_cnt_column = find_column(&headers, &schema.mult_cnt);
+    let dmag_column = find_column(&headers, &schema.dmag);
+    let pm_columns = find_column(&headers, &schema.pm_ra).zip(find_column(&headers, &schema.pm_dec));
+    let b_v_column = find_column(&headers, &schema.b_v);
+    let sp_type_column = find_column(&headers, &schema.sp_type);
+
+    let mut stars: Vec<Star> = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+
+        let (lat, lon) = match position {
+            PositionColumns::Galactic { glon, glat } => {
+                match (parse_csv_field::<f64>(&record, glat, &schema.glat)?, parse_csv_field::<f64>(&record, glon, &schema.glon)?) {
+                    (Some(lat), Some(lon)) => (lat, lon),
+                    _ => continue,
+                }
+            }
+            PositionColumns::Equatorial { ra, dec } => {
+                match (parse_csv_field::<f64>(&record, ra, &schema.ra)?, parse_csv_field::<f64>(&record, dec, &schema.dec)?) {
+                    (Some(ra), Some(dec)) => {
+                        let (glon, glat) = equatorial_to_galactic(ra, dec);
+                        (glat, glon)
+                    }
+                    _ => continue,
+                }
+            }
+        };
+
+        let line = record.position().map_or(0, |position| position.line());
+        let hr = parse_csv_field(&record, hr_column, &schema.hr)?
+            .ok_or_else(|| SatSightError::parse(line, &schema.hr, "missing value"))?;
+        let vmag = parse_csv_field(&record, mag_column, &schema.mag)?
+            .ok_or_else(|| SatSightError::parse(line, &schema.mag, "missing value"))?;
+        let (mag, band) = match model {
+            Some(model) => {
+                let b_v = match b_v_column {
+                    Some(column) => parse_csv_field(&record, column, &schema.b_v)?,
+                    None => None,
+                };
+                let sp_type = sp_type_column.and_then(|column| record.get(column)).unwrap_or("").trim();
+                (model.sensor_magnitude(vmag, b_v, sp_type), MagnitudeBand::Sensor)
+            }
+            None => (vmag, MagnitudeBand::V),
+        };
+
+        let proper_motion = match pm_columns {
+            Some((pm_ra, pm_dec)) => Some((
+                parse_csv_field(&record, pm_ra, &schema.pm_ra)?.unwrap_or(0.0),
+                parse_csv_field(&record, pm_dec, &schema.pm_dec)?.unwrap_or(0.0),
+            )),
+            None => None,
+        };
+        let flags = quality_flags(
+            has_value(&record, var_id_column),
+            has_value(&record, multiple_column) || has_value(&record, mult_cnt_column) || has_value(&record, dmag_column),
+            proper_motion,
+            b_v_column.is_some() && !has_value(&record, b_v_column),
+        );
+
+        stars.push(Star {
+            hr,
+            id: StarId::from_hr(hr),
+            lat,
+            lon,
+            mag,
+            band,
+            fingure_print: 0.0,
+            flags,
+        });
+    }
+    Ok(stars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn raw_file(name: &str) -> File {
+        File::open(format!("{}/data/raw/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+    }
+
+    // Records of bsc5.dat: a multiple star, a single star and two nova placeholders
+    const BSC5_SAMPLE: [&str; 4] = [
+        "   1          BD+44 4550      3 36042          46           000001.1+444022000509.9+451345114.44-16.88 6.70  +0.07 +0.08         A1Vn               -0.012-0.018      -018      195  4.2  21.6AC   3",
+        "   7 10    CasBD+63 2107    144 109782005                   000114.4+633822000626.5+641146118.06  1.75 5.59  -0.03 -0.19         B9III             e+0.008 0.000      -000V     153                 *",
+        "  92 NOVA 1572                                     B Cas                                                                                                                                            *",
+        "  95 47    Tuc                                                                                                                                                                                      *",
+    ];
+
+    #[test]
+    fn bsc5_records_parse_and_placeholders_are_skipped() {
+        let records: Vec<Option<BscStar>> = (1..)
+            .zip(BSC5_SAMPLE)
+            .map(|(line_number, line)| parse_bsc5_record(line, line_number).unwrap())
+            .collect();
+        assert!(records[2].is_none() && records[3].is_none());
+
+        let hr1 = records[0].as_ref().unwrap();
+        assert_eq!((hr1.hr, hr1.hd, hr1.vmag, hr1.b_v), (1, Some(3), 6.70, Some(0.07)));
+        assert!((hr1.ra - (5.0 / 60.0 + 9.9 / 3600.0) * 15.0).abs() < 1e-12);
+        assert!((hr1.dec - (45.0 + 13.0 / 60.0 + 45.0 / 3600.0)).abs() < 1e-12);
+        assert_eq!((hr1.glon, hr1.glat), (114.44, -16.88));
+        assert_eq!((hr1.pm_ra, hr1.pm_dec), (-0.012, -0.018));
+        assert_eq!((hr1.dmag, hr1.sep, hr1.mult_id.as_str(), hr1.mult_cnt), (Some(4.2), Some(21.6), "AC", Some(3)));
+        assert_eq!(hr1.sp_type, "A1Vn");
+
+        let hr7 = records[1].as_ref().unwrap();
+        assert_eq!((hr7.hr, hr7.name.as_str(), hr7.var_id.as_str()), (7, "10    Cas", ""));
+        assert_eq!((hr7.glat, hr7.b_v, hr7.dynamical_parallax), (1.75, Some(-0.03), false));
+        assert!(hr7.dmag.is_none() && hr7.mult_cnt.is_none());
+    }
+
+    #[test]
+    fn bsc5_file_skips_the_14_placeholder_records() {
+        let lines = std::fs::read_to_string(format!("{}/data/raw/bsc5.dat", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        assert_eq!(lines.lines().count(), 9110);
+        assert_eq!(parse_bsc5_file(raw_file("bsc5.dat")).unwrap().len(), 9110 - 14);
+    }
+
+    // The first rows of formated_no_nova.csv and formated_no_nova_short.csv, which swaps GLAT and GLON,
+    // with a nova placeholder that has no position
+    const GLON_FIRST: &str = "HR,GLON,GLAT,Vmag\n1,114.44,-16.88,6.7\n2,98.33,-61.14,6.29\n92,,,\n";
+    const GLAT_FIRST: &str = "HR,GLAT,GLON,Vmag\n1,-16.88,114.44,6.7\n2,-61.14,98.33,6.29\n92,,,\n";
+
+    fn positions(stars: &[Star]) -> Vec<(u32, f64, f64, f32)> {
+        stars.iter().map(|star| (star.hr, star.lat, star.lon, star.mag)).collect()
+    }
+
+    #[test]
+    fn csv_columns_are_found_by_header_name() {
+        let schema = CatalogSchema::default();
+        let glon_first = parse_csv_catalog(GLON_FIRST.as_bytes(), &schema, None).unwrap();
+        let glat_first = parse_csv_catalog(GLAT_FIRST.as_bytes(), &schema, None).unwrap();
+
+        assert_eq!(positions(&glon_first), vec![(1, -16.88, 114.44, 6.7), (2, -61.14, 98.33, 6.29)]);
+        assert_eq!(positions(&glat_first), positions(&glon_first));
+
+        // Header names ignore case and surrounding blanks
+        let lower_case = parse_csv_catalog("hr, glat ,GLon,VMAG\n1,-16.88,114.44,6.7\n".as_bytes(), &schema, None).unwrap();
+        assert_eq!(positions(&lower_case), positions(&glon_first[..1]));
+    }
+
+    #[test]
+    fn csv_schema_maps_other_headers_and_equatorial_positions() {
+        let schema = CatalogSchema::parse("hr=HIP, ra=RAdeg, dec=DEdeg, mag=Vmag").unwrap();
+        let stars = parse_csv_catalog("DEdeg,Vmag,RAdeg,HIP\n45.22916667,6.70,1.29125,0\n".as_bytes(), &schema, None).unwrap();
+        let (glon, glat) = equatorial_to_galactic(1.29125, 45.22916667);
+        assert_eq!(positions(&stars), vec![(0, glat, glon, 6.7)]);
+        assert_eq!(stars[0].id, StarId::Unknown);
+
+        assert!(CatalogSchema::parse("hr HIP").is_err());
+        assert!(CatalogSchema::parse("colour=B-V").is_err());
+        assert!(parse_csv_catalog("GLON,GLAT,Vmag\n114.44,-16.88,6.7\n".as_bytes(), &CatalogSchema::default(), None).is_err());
+    }
+
+    #[test]
+    fn bsc5_and_yale_csv_give_the_same_flags() {
+        let fixed_width: HashMap<u32, u32> = parse_bsc5_star_file(raw_file("bsc5.dat"))
+            .unwrap()
+            .iter()
+            .map(|star| (star.hr, star.flags))
+            .collect();
+        let csv = parse_star_file_with_schema(raw_file("yale_bright_star_catalog_v5.csv"), &CatalogSchema::default()).unwrap();
+
+        assert_eq!(csv.len(), fixed_width.len());
+        for star in &csv {
+            assert_eq!(Some(&star.flags), fixed_width.get(&star.hr), "HR {}", star.hr);
+        }
+    }
+
+    #[test]
+    fn csv_sensor_magnitudes_match_bsc5() {
+        let model = SensorModel::default();
+        let fixed_width: HashMap<u32, Star> = parse_bsc5_file(raw_file("bsc5.dat"))
+            .unwrap()
+            .iter()
+            .map(|star| (star.hr, star.to_sensor_star(&model)))
+            .collect();
+        let schema = CatalogSchema::default();
+
+        let csv = parse_sensor_star_file_with_schema(raw_file("yale_bright_star_catalog_v5.csv"), &schema, &model).unwrap();
+        assert_eq!(csv.len(), fixed_width.len());
+        for star in &csv {
+            let expected = &fixed_width[&star.hr];
+            assert_eq!((star.band, expected.band), (MagnitudeBand::Sensor, MagnitudeBand::Sensor));
+            assert!((star.mag - expected.mag).abs() < 1e-4, "HR {}: {} and {}", star.hr, star.mag, expected.mag);
+        }
+
+        let v_band = parse_star_file_with_schema(raw_file("yale_bright_star_catalog_v5.csv"), &schema).unwrap();
+        assert!(v_band.iter().all(|star| star.band == MagnitudeBand::V));
+    }
+}
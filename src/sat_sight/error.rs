@@ -0,0 +1,97 @@
+//! Error type shared by every fallible function in `sat_sight`.
+//!
+//! Nothing in the module panics on bad input; a malformed catalog record,
+//! a blank frame or a degenerate geometry comes back as a `SatSightError`.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SatSightError {
+    /// Reading a file failed
+    Io(std::io::Error),
+    /// The csv reader could not read a record
+    Csv(csv::Error),
+    /// A catalog record could not be parsed
+    CatalogParse {
+        line: u64,      // 1 based line number in the catalog file
+        column: String, // Header name or byte range of the field
+        message: String,
+    },
+    /// A catalog schema description is malformed
+    InvalidSchema(String),
+    /// A binary catalog has a bad header, size or checksum
+    InvalidBinaryCatalog(String),
+    /// A spectral response curve is malformed
+    InvalidResponseCurve(String),
+    /// A star's magnitude is not in the band the operation works in
+    WrongMagnitudeBand(String),
+    /// Decoding or encoding an image failed
+    Image(image::ImageError),
+    /// Every pixel of the image has the same value so there is nothing to find
+    FlatImage,
+    /// The inputs do not define a usable geometry, e.g. a zero length vector
+    DegenerateGeometry(String),
+}
+
+impl SatSightError {
+    pub(crate) fn parse(line: u64, column: &str, message: impl Into<String>) -> Self {
+        SatSightError::CatalogParse {
+            line,
+            column: column.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn degenerate(message: impl Into<String>) -> Self {
+        SatSightError::DegenerateGeometry(message.into())
+    }
+}
+
+impl fmt::Display for SatSightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SatSightError::Io(err) => write!(f, "io error: {}", err),
+            SatSightError::Csv(err) => write!(f, "csv error: {}", err),
+            SatSightError::CatalogParse { line, column, message } => {
+                write!(f, "catalog line {}, column {}: {}", line, column, message)
+            }
+            SatSightError::InvalidSchema(message) => write!(f, "invalid catalog schema: {}", message),
+            SatSightError::InvalidBinaryCatalog(message) => write!(f, "invalid binary catalog: {}", message),
+            SatSightError::InvalidResponseCurve(message) => write!(f, "invalid response curve: {}", message),
+            SatSightError::WrongMagnitudeBand(message) => write!(f, "wrong magnitude band: {}", message),
+            SatSightError::Image(err) => write!(f, "image error: {}", err),
+            SatSightError::FlatImage => write!(f, "image has no contrast"),
+            SatSightError::DegenerateGeometry(message) => write!(f, "degenerate geometry: {}", message),
+        }
+    }
+}
+
+impl Error for SatSightError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SatSightError::Io(err) => Some(err),
+            SatSightError::Csv(err) => Some(err),
+            SatSightError::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SatSightError {
+    fn from(err: std::io::Error) -> Self {
+        SatSightError::Io(err)
+    }
+}
+
+impl From<csv::Error> for SatSightError {
+    fn from(err: csv::Error) -> Self {
+        SatSightError::Csv(err)
+    }
+}
+
+impl From<image::ImageError> for SatSightError {
+    fn from(err: image::ImageError) -> Self {
+        SatSightError::Image(err)
+    }
+}
@@ -0,0 +1,220 @@
+//! Celestial reference frames and the rotations between them.
+//!
+//! The star pipeline runs in galactic coordinates while attitude is usually
+//! exchanged in ICRF/J2000 equatorial coordinates. The matrices here are built
+//! from the defining angles of each frame rather than from rounded tables, so
+//! going back and forth between frames is exact to double precision.
+//!
+//! All matrices are passive: they take the components of a vector expressed in
+//! one frame to the components of the same vector expressed in another frame.
+
+use nalgebra::{Matrix3, Quaternion, Vector3};
+
+use super::error::SatSightError;
+use super::precession::{precession_matrix, precession_nutation_matrix};
+use super::{rotation_matrix_to_quaternion, Star, StarVec};
+
+/// Right ascension of the north galactic pole, ICRS (deg), Hipparcos vol 1 sec 1.5.3
+pub const GALACTIC_POLE_RA: f64 = 192.859_48;
+/// Declination of the north galactic pole, ICRS (deg)
+pub const GALACTIC_POLE_DEC: f64 = 27.128_25;
+/// Galactic longitude of the north celestial pole (deg)
+pub const GALACTIC_LON_OF_CELESTIAL_POLE: f64 = 122.931_92;
+/// Mean obliquity of the ecliptic at J2000.0, IAU 2006 (arcsec)
+pub const OBLIQUITY_J2000_ARCSEC: f64 = 84_381.406;
+
+/// A celestial reference frame, all of them are inertial and share the same origin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Frame {
+    Equatorial,      // ICRF / J2000 equatorial, lat = Dec, lon = RA
+    Galactic,        // IAU 1958 galactic as defined for Hipparcos, lat = GLAT, lon = GLON
+    Ecliptic,        // Mean ecliptic and equinox of J2000, lat = beta, lon = lambda
+    MeanOfDate(f64), // Mean equator and equinox of the Julian date (TT)
+    TrueOfDate(f64), // True equator and equinox of the Julian date (TT)
+}
+
+// ==== Rotation Matrices =========================================================================
+// Elementary rotations and the fixed rotations between the frames
+// ================================================================================================
+
+/// Passive rotation of the coordinate axes about x by `angle` radians
+pub fn rot_x(angle: f64) -> Matrix3<f64> {
+    let (s, c) = angle.sin_cos();
+    Matrix3::new(
+        1.0, 0.0, 0.0,
+        0.0, c, s,
+        0.0, -s, c,
+    )
+}
+
+/// Passive rotation of the coordinate axes about y by `angle` radians
+pub fn rot_y(angle: f64) -> Matrix3<f64> {
+    let (s, c) = angle.sin_cos();
+    Matrix3::new(
+        c, 0.0, -s,
+        0.0, 1.0, 0.0,
+        s, 0.0, c,
+    )
+}
+
+/// Passive rotation of the coordinate axes about z by `angle` radians
+pub fn rot_z(angle: f64) -> Matrix3<f64> {
+    let (s, c) = angle.sin_cos();
+    Matrix3::new(
+        c, s, 0.0,
+        -s, c, 0.0,
+        0.0, 0.0, 1.0,
+    )
+}
+
+/// Equatorial (ICRF) to galactic rotation built from the galactic pole and node
+pub fn equatorial_to_galactic_matrix() -> Matrix3<f64> {
+    rot_z(std::f64::consts::PI - GALACTIC_LON_OF_CELESTIAL_POLE.to_radians())
+        * rot_y(std::f64::consts::FRAC_PI_2 - GALACTIC_POLE_DEC.to_radians())
+        * rot_z(GALACTIC_POLE_RA.to_radians())
+}
+
+/// Equatorial (ICRF) to J2000 mean ecliptic rotation
+pub fn equatorial_to_ecliptic_matrix() -> Matrix3<f64> {
+    rot_x((OBLIQUITY_J2000_ARCSEC / 3600.0).to_radians())
+}
+
+/// Rotation from a frame to the equatorial frame
+fn to_equatorial_matrix(frame: Frame) -> Matrix3<f64> {
+    match frame {
+        Frame::Equatorial => Matrix3::identity(),
+        Frame::Galactic => equatorial_to_galactic_matrix().transpose(),
+        Frame::Ecliptic => equatorial_to_ecliptic_matrix().transpose(),
+        Frame::MeanOfDate(jd) => precession_matrix(jd).transpose(),
+        Frame::TrueOfDate(jd) => precession_nutation_matrix(jd).transpose(),
+    }
+}
+
+/// Returns the matrix taking vector components in `from` to components in `to`
+pub fn frame_matrix(from: Frame, to: Frame) -> Matrix3<f64> {
+    to_equatorial_matrix(to).transpose() * to_equatorial_matrix(from)
+}
+
+// ==== Conversions ===============================================================================
+// Frame conversions for vectors, directions, stars and attitudes
+// ================================================================================================
+
+/// Converts a latitude and longitude in degrees to a unit vector
+pub fn lat_lon_to_unit_vector(lat: f64, lon: f64) -> Vector3<f64> {
+    let (lat, lon) = (lat.to_radians(), lon.to_radians());
+    Vector3::new(lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin())
+}
+
+/// Converts a vector to a latitude and longitude in degrees, longitude in [0, 360)
+pub fn unit_vector_to_lat_lon(v: &Vector3<f64>) -> Result<(f64, f64), SatSightError> {
+    let r = v.norm();
+    if r == 0.0 || !r.is_finite() {
+        return Err(SatSightError::degenerate("vector has no direction"));
+    }
+    let lat = (v.z / r).clamp(-1.0, 1.0).asin().to_degrees();
+    let lon = v.y.atan2(v.x).to_degrees().rem_euclid(360.0);
+    Ok((lat, lon))
+}
+
+/// Converts vector components from one frame to another
+pub fn convert_vector(v: &Vector3<f64>, from: Frame, to: Frame) -> Vector3<f64> {
+    frame_matrix(from, to) * v
+}
+
+/// Converts a (lat, lon) direction in degrees from one frame to another
+pub fn convert_direction(lat: f64, lon: f64, from: Frame, to: Frame) -> (f64, f64) {
+    let v = convert_vector(&lat_lon_to_unit_vector(lat, lon), from, to);
+    // A rotated unit vector can not be zero length
+    unit_vector_to_lat_lon(&v).unwrap_or((lat, lon))
+}
+
+/// Converts equatorial J2000 coordinates to galactic coordinates, returns (glon, glat) in degrees
+pub fn equatorial_to_galactic(ra: f64, dec: f64) -> (f64, f64) {
+    let (glat, glon) = convert_direction(dec, ra, Frame::Equatorial, Frame::Galactic);
+    (glon, glat)
+}
+
+/// Converts galactic coordinates to equatorial J2000 coordinates, returns (ra, dec) in degrees
+pub fn galactic_to_equatorial(glon: f64, glat: f64) -> (f64, f64) {
+    let (dec, ra) = convert_direction(glat, glon, Frame::Galactic, Frame::Equatorial);
+    (ra, dec)
+}
+
+/// Returns a copy of the star with its lat/lon expressed in another frame
+pub fn convert_star(star: &Star, from: Frame, to: Frame) -> Star {
+    let (lat, lon) = convert_direction(star.lat, star.lon, from, to);
+    Star {
+        lat,
+        lon,
+        ..star.clone()
+    }
+}
+
+/// Returns the star vector with its components expressed in another frame
+pub fn convert_star_vec(star_vec: &StarVec, from: Frame, to: Frame) -> StarVec {
+    let converted = convert_vector(&star_vec.to_vector(), from, to);
+    StarVec {
+        x: converted.x,
+        y: converted.y,
+        z: converted.z,
+        ..star_vec.clone()
+    }
+}
+
+/// Re-expresses an attitude quaternion in another reference frame.
+///
+/// `attitude` rotates camera frame vectors into the `from` frame (as `rotate_vector` applies
+/// it, `q * v * q_conj`); the result rotates camera frame vectors into the `to` frame.
+pub fn convert_attitude(attitude: &Quaternion<f64>, from: Frame, to: Frame) -> Quaternion<f64> {
+    rotation_matrix_to_quaternion(&frame_matrix(from, to)) * attitude
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat_sight::attitude::Attitude;
+    use crate::sat_sight::rotate_vector;
+
+    #[test]
+    fn hr_1_has_the_bsc_galactic_position() {
+        // BSC5 HR 1: 00 05 09.9 +45 13 45 (J2000), GLON 114.44, GLAT -16.88
+        let ra = (5.0 * 60.0 + 9.9) / 3600.0 * 15.0;
+        let dec = 45.0 + 13.0 / 60.0 + 45.0 / 3600.0;
+        let (glon, glat) = equatorial_to_galactic(ra, dec);
+        assert!((glon - 114.44).abs() < 0.006 && (glat + 16.88).abs() < 0.006, "{} {}", glon, glat);
+    }
+
+    #[test]
+    fn galactic_centre_is_in_sagittarius() {
+        let (ra, dec) = galactic_to_equatorial(0.0, 0.0);
+        assert!((ra - 266.405).abs() < 5e-4 && (dec + 28.936).abs() < 5e-4, "{} {}", ra, dec);
+    }
+
+    #[test]
+    fn fixed_frames_round_trip() {
+        let frames = [Frame::Equatorial, Frame::Galactic, Frame::Ecliptic];
+        for (lat, lon) in [(0.0, 0.0), (45.2, 1.3), (-89.9, 200.0), (27.1, 192.9), (-60.0, 300.0)] {
+            let v = lat_lon_to_unit_vector(lat, lon);
+            for from in frames {
+                for to in frames {
+                    let there = convert_vector(&v, from, to);
+                    assert!((convert_vector(&there, to, from) - v).norm() < 1e-12);
+                }
+            }
+            let cycle = convert_vector(&convert_vector(&convert_vector(&v, Frame::Equatorial, Frame::Galactic), Frame::Galactic, Frame::Ecliptic), Frame::Ecliptic, Frame::Equatorial);
+            assert!((cycle - v).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn converted_attitude_keeps_the_boresight_on_the_same_star() {
+        let attitude = Attitude::from_ra_dec_roll(266.405, -28.936, 30.0);
+        let galactic = convert_attitude(&attitude.quaternion(), Frame::Equatorial, Frame::Galactic);
+
+        let boresight = Vector3::new(0.0, 0.0, 1.0);
+        let expected = convert_vector(&attitude.rotate(&boresight), Frame::Equatorial, Frame::Galactic);
+        assert!((rotate_vector(&galactic, &boresight) - expected).norm() < 1e-12);
+        // The boresight points at the galactic centre
+        assert!((rotate_vector(&galactic, &boresight) - Vector3::new(1.0, 0.0, 0.0)).norm() < 1e-5);
+    }
+}
@@ -0,0 +1,149 @@
+//! Guide star catalog generation.
+//!
+//! The full BSC wastes memory and crowds the dense Milky Way fields with
+//! stars, while sparse fields need every star they have to be solvable. The
+//! selection here keeps the brightest stars of every sky region up to a
+//! target density per field of view, which evens out the catalog across the
+//! sphere, and drops stars too close to a brighter one to be told apart.
+
+use nalgebra::Vector3;
+
+use super::error::SatSightError;
+use super::frames::lat_lon_to_unit_vector;
+use super::Star;
+
+/// Parameters of the guide star selection
+#[derive(Clone, Debug)]
+pub struct GuideStarConfig {
+    pub limiting_mag: f32,    // Faintest magnitude that is kept, in the band of `Star::mag`
+    pub min_separation: f64,  // Minimum angle between kept stars (deg)
+    pub fov: f64,             // Field of view of the camera (deg)
+    pub stars_per_fov: usize, // Target number of stars in a field of view
+}
+
+/// Builds a reduced guide star catalog.
+///
+/// Stars are considered from brightest to faintest. A star is kept when it is
+/// brighter than the limiting magnitude, no kept star lies within the minimum
+/// separation, and fewer than `stars_per_fov` kept stars lie within a field of
+/// view centred on it. Dense regions therefore fill up with their brightest
+/// stars while sparse regions keep everything above the magnitude limit.
+///
+/// Brightness comes from `Star::mag`, so stars built with `to_sensor_star` are selected by
+/// their predicted sensor brightness. The result is ordered from brightest to faintest.
+pub fn select_guide_stars(stars: &[Star], config: &GuideStarConfig) -> Result<Vec<Star>, SatSightError> {
+    if !(config.fov > 0.0 && config.fov < 180.0) {
+        return Err(SatSightError::degenerate("field of view has to be between 0 and 180 degrees"));
+    }
+    if config.stars_per_fov == 0 {
+        return Err(SatSightError::degenerate("at least one star per field of view is needed"));
+    }
+
+    let mut candidates: Vec<&Star> = stars
+        .iter()
+        .filter(|star| star.mag <= config.limiting_mag)
+        .collect();
+    candidates.sort_by(|a, b| a.mag.total_cmp(&b.mag));
+
+    // Compare cosines instead of angles, a larger cosine is a smaller angle
+    let cos_min_separation = config.min_separation.max(0.0).to_radians().cos();
+    let cos_half_fov = (config.fov / 2.0).to_radians().cos();
+
+    let mut kept: Vec<(Vector3<f64>, Star)> = Vec::new();
+    for star in candidates {
+        let direction = lat_lon_to_unit_vector(star.lat, star.lon);
+
+        let mut too_close = false;
+        let mut in_field = 0;
+        for (other, _) in &kept {
+            let cos_angle = direction.dot(other);
+            if config.min_separation > 0.0 && cos_angle >= cos_min_separation {
+                too_close = true;
+                break;
+            }
+            if cos_angle >= cos_half_fov {
+                in_field += 1;
+            }
+        }
+
+        if !too_close && in_field < config.stars_per_fov {
+            kept.push((direction, star.clone()));
+        }
+    }
+
+    Ok(kept.into_iter().map(|(_, star)| star).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat_sight::photometry::MagnitudeBand;
+    use crate::sat_sight::StarId;
+
+    fn star(hr: u32, lat: f64, lon: f64, mag: f32) -> Star {
+        Star {
+            hr,
+            id: StarId::from_hr(hr),
+            lat,
+            lon,
+            mag,
+            band: MagnitudeBand::V,
+            fingure_print: 0.0,
+            flags: 0,
+        }
+    }
+
+    fn config(limiting_mag: f32, min_separation: f64, stars_per_fov: usize) -> GuideStarConfig {
+        GuideStarConfig { limiting_mag, min_separation, fov: 10.0, stars_per_fov }
+    }
+
+    fn hrs(stars: &[Star]) -> Vec<u32> {
+        stars.iter().map(|star| star.hr).collect()
+    }
+
+    #[test]
+    fn faint_stars_are_cut_and_the_rest_sorted() {
+        let stars: Vec<Star> = (1..=6).map(|hr| star(hr, 0.0, hr as f64 * 30.0, 9.0 - hr as f32)).collect();
+        let selected = select_guide_stars(&stars, &config(6.0, 0.0, 10)).unwrap();
+        // Magnitudes 3 to 8, everything at or brighter than 6 is kept
+        assert_eq!(hrs(&selected), [6, 5, 4, 3]);
+    }
+
+    #[test]
+    fn stars_closer_than_the_minimum_separation_keep_the_brighter_one() {
+        let stars = [star(1, 10.0, 10.0, 4.0), star(2, 10.0, 10.05, 3.0), star(3, 10.0, 10.3, 5.0)];
+        // 0.05 deg is too close, 0.25 deg from the kept star is not
+        assert_eq!(hrs(&select_guide_stars(&stars, &config(6.0, 0.1, 10)).unwrap()), [2, 3]);
+        assert_eq!(hrs(&select_guide_stars(&stars, &config(6.0, 0.0, 10)).unwrap()), [2, 1, 3]);
+    }
+
+    #[test]
+    fn dense_fields_are_capped_and_sparse_fields_kept() {
+        // Twenty stars in a square degree of the galactic plane and three stars far from it
+        let mut stars: Vec<Star> = (0..20)
+            .map(|i| star(i + 1, (i % 5) as f64 * 0.2, (i / 5) as f64 * 0.2, 2.0 + i as f32 * 0.1))
+            .collect();
+        stars.extend([star(101, 60.0, 100.0, 5.5), star(102, 62.0, 100.0, 5.8), star(103, -60.0, 250.0, 5.9)]);
+
+        let selected = select_guide_stars(&stars, &config(6.0, 0.0, 5)).unwrap();
+        // The five brightest of the dense field, every star of the sparse fields
+        assert_eq!(hrs(&selected), [1, 2, 3, 4, 5, 101, 102, 103]);
+
+        let cos_half_fov = 5_f64.to_radians().cos();
+        for kept in &selected {
+            let direction = lat_lon_to_unit_vector(kept.lat, kept.lon);
+            let in_field = selected
+                .iter()
+                .filter(|other| direction.dot(&lat_lon_to_unit_vector(other.lat, other.lon)) >= cos_half_fov)
+                .count();
+            assert!(in_field <= 5);
+        }
+    }
+
+    #[test]
+    fn a_degenerate_config_is_an_error() {
+        let stars = [star(1, 0.0, 0.0, 1.0)];
+        assert!(select_guide_stars(&stars, &GuideStarConfig { fov: 0.0, ..config(6.0, 0.0, 5) }).is_err());
+        assert!(select_guide_stars(&stars, &config(6.0, 0.0, 0)).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Merging of double and multiple stars the camera can not resolve.
+//!
+//! Two stars closer than the camera's angular resolution show up as a single
+//! blob at their photocentre, not at the position of either star. Catalog
+//! entries closer than the resolution are merged into one entry at the flux
+//! weighted position with the combined magnitude. An entry whose own listed
+//! companion (BSC `Sep`/`Dmag`) is unresolved already carries `FLAG_MULTIPLE`
+//! from the catalog; the BSC has no position angle for the companion so its
+//! position is left alone.
+
+use nalgebra::Vector3;
+
+use super::catalog::BscStar;
+use super::frames::{lat_lon_to_unit_vector, unit_vector_to_lat_lon};
+use super::photometry::MagnitudeBand;
+use super::{Star, StarId, FLAG_MULTIPLE};
+
+/// How a catalog entry came out of `merge_unresolved_multiples`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Multiplicity {
+    Single, // No other catalog entry within the resolution
+    Merged, // Several catalog entries merged into this one
+}
+
+/// A catalog entry after merging
+#[derive(Clone, Debug)]
+pub struct MergedStar {
+    pub star: Star,                 // Photocentre and combined magnitude
+    pub members: Vec<u32>,          // HR numbers of the merged entries, brightest first
+    pub multiplicity: Multiplicity,
+}
+
+/// Flux relative to a magnitude 0 star
+pub fn magnitude_to_flux(mag: f32) -> f64 {
+    10_f64.powf(-0.4 * mag as f64)
+}
+
+/// Magnitude of a flux relative to a magnitude 0 star
+pub fn flux_to_magnitude(flux: f64) -> f32 {
+    (-2.5 * flux.log10()) as f32
+}
+
+/// Combined magnitude of several stars seen as one
+pub fn combined_magnitude(mags: &[f32]) -> f32 {
+    flux_to_magnitude(mags.iter().map(|mag| magnitude_to_flux(*mag)).sum())
+}
+
+/// Finds the root of a union-find set, compressing the path on the way
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+/// Merges catalog entries that are closer than the camera's angular `resolution` (deg).
+///
+/// Groups are built transitively, so a chain of close stars becomes one entry. A merged
+/// entry takes the HR number of its brightest member and sits at the flux weighted mean
+/// of the members' directions.
+pub fn merge_unresolved_multiples(stars: &[BscStar], resolution: f64) -> Vec<MergedStar> {
+    let directions: Vec<Vector3<f64>> = stars
+        .iter()
+        .map(|star| lat_lon_to_unit_vector(star.glat, star.glon))
+        .collect();
+    let cos_resolution = resolution.to_radians().cos();
+
+    // Sweep in latitude order so only stars within the resolution in latitude are compared
+    let mut order: Vec<usize> = (0..stars.len()).collect();
+    order.sort_by(|a, b| stars[*a].glat.total_cmp(&stars[*b].glat));
+
+    let mut parents: Vec<usize> = (0..stars.len()).collect();
+    for (position, &i) in order.iter().enumerate() {
+        for &j in &order[position + 1..] {
+            if stars[j].glat - stars[i].glat > resolution {
+                break;
+            }
+            if directions[i].dot(&directions[j]) >= cos_resolution {
+                let (root_i, root_j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[root_i] = root_j;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = vec![Vec::new(); stars.len()];
+    for index in 0..stars.len() {
+        let root = find_root(&mut parents, index);
+        groups[root].push(index);
+    }
+
+    groups
+        .into_iter()
+        .filter(|group| !group.is_empty())
+        .map(|mut group| {
+            group.sort_by(|a, b| stars[*a].vmag.total_cmp(&stars[*b].vmag));
+            let primary = &stars[group[0]];
+
+            if group.len() == 1 {
+                return MergedStar {
+                    star: primary.to_star(),
+                    members: vec![primary.hr],
+                    multiplicity: Multiplicity::Single,
+                };
+            }
+
+            let mut photocentre = Vector3::zeros();
+            let mut total_flux = 0.0;
+            for &index in &group {
+                let flux = magnitude_to_flux(stars[index].vmag);
+                photocentre += directions[index] * flux;
+                total_flux += flux;
+            }
+            // The members are within the resolution of each other so the sum can not cancel out
+            let (lat, lon) = unit_vector_to_lat_lon(&photocentre).unwrap_or((primary.glat, primary.glon));
+
+            MergedStar {
+                star: Star {
+                    hr: primary.hr,
+                    id: StarId::Hr(primary.hr),
+                    lat,
+                    lon,
+                    mag: flux_to_magnitude(total_flux),
+                    band: MagnitudeBand::V,
+                    fingure_print: 0.0,
+                    flags: group.iter().fold(FLAG_MULTIPLE, |flags, index| flags | stars[*index].flags()),
+                },
+                members: group.iter().map(|index| stars[*index].hr).collect(),
+                multiplicity: Multiplicity::Merged,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bsc_star(hr: u32, glat: f64, glon: f64, vmag: f32) -> BscStar {
+        BscStar {
+            hr,
+            name: String::new(),
+            hd: None,
+            multiple: None,
+            var_id: String::new(),
+            ra: 0.0,
+            dec: 0.0,
+            glon,
+            glat,
+            vmag,
+            b_v: Some(0.0),
+            sp_type: String::new(),
+            pm_ra: 0.0,
+            pm_dec: 0.0,
+            parallax: None,
+            dynamical_parallax: false,
+            dmag: None,
+            sep: None,
+            mult_id: String::new(),
+            mult_cnt: None,
+        }
+    }
+
+    #[test]
+    fn two_equal_stars_are_0_753_mag_brighter_at_their_midpoint() {
+        let stars = [bsc_star(1, 10.0, 20.0, 5.0), bsc_star(2, 10.0, 20.01, 5.0)];
+        let merged = merge_unresolved_multiples(&stars, 0.02);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].multiplicity, Multiplicity::Merged);
+        assert!((merged[0].star.mag - (5.0 - 0.753)).abs() < 1e-3);
+        assert!((merged[0].star.lat - 10.0).abs() < 1e-6 && (merged[0].star.lon - 20.005).abs() < 1e-9);
+        assert_eq!(merged[0].star.flags & FLAG_MULTIPLE, FLAG_MULTIPLE);
+        assert!((combined_magnitude(&[5.0, 5.0]) - 4.247).abs() < 1e-3);
+    }
+
+    #[test]
+    fn the_photocentre_is_flux_weighted() {
+        // A 2.5 mag fainter companion has a tenth of the flux and pulls the centre an eleventh of the way
+        let stars = [bsc_star(7, 0.0, 100.011, 3.5), bsc_star(8, 0.0, 100.0, 1.0)];
+        let merged = merge_unresolved_multiples(&stars, 0.02);
+        assert_eq!(merged.len(), 1);
+        assert_eq!((merged[0].star.hr, merged[0].members.as_slice()), (8, [8, 7].as_slice()));
+        assert!((merged[0].star.lon - 100.001).abs() < 1e-9 && merged[0].star.lat.abs() < 1e-9);
+        assert!((merged[0].star.mag - combined_magnitude(&[1.0, 3.5])).abs() < 1e-6);
+    }
+
+    #[test]
+    fn resolved_stars_and_listed_companions_stay_single() {
+        // The first entry lists a close companion that is not in the catalog
+        let double = BscStar { dmag: Some(1.2), sep: Some(0.5), multiple: Some('A'), ..bsc_star(1, 0.0, 0.0, 4.0) };
+        let stars = [double.clone(), bsc_star(2, 0.0, 0.05, 4.0)];
+        let merged = merge_unresolved_multiples(&stars, 0.02);
+
+        assert_eq!(merged.len(), 2);
+        assert!(merged.iter().all(|entry| entry.multiplicity == Multiplicity::Single));
+        let single = merged.iter().find(|entry| entry.star.hr == 1).unwrap();
+        assert_eq!((single.star.lat, single.star.lon, single.star.mag), (0.0, 0.0, 4.0));
+        assert_eq!(single.star.flags, double.flags());
+        assert_eq!(single.star.flags & FLAG_MULTIPLE, FLAG_MULTIPLE);
+    }
+}
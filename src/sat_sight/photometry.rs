@@ -0,0 +1,328 @@
+//! Sensor band magnitudes.
+//!
+//! Catalog magnitudes are Johnson V, but a silicon detector is far more
+//! sensitive in the red, so K and M stars look much brighter on the sensor
+//! than their V magnitude says. The star is treated as a black body at the
+//! temperature given by its B-V colour (or spectral type when there is no
+//! colour) and its photon flux through the sensor's response curve is
+//! compared with the flux through the V band. The difference is zero for an
+//! A0 star (B-V = 0), so sensor magnitudes stay close to V for white stars.
+
+use csv::Reader;
+use serde::Serialize;
+use std::fs::File;
+
+use super::catalog::{parse_csv_field, require_column};
+use super::error::SatSightError;
+use super::Star;
+
+/// Planck's constant times the speed of light over Boltzmann's constant (nm K)
+const SECOND_RADIATION_CONSTANT: f64 = 1.438_777e7;
+
+// Range and step of the precomputed colour terms
+const B_V_MIN: f32 = -0.40;
+const B_V_MAX: f32 = 2.20;
+const B_V_STEP: f32 = 0.02;
+
+/// Band a magnitude is given in, recorded on every `Star` so V and sensor magnitudes do not mix
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum MagnitudeBand {
+    #[default]
+    V,      // Johnson V, the band of the catalogs
+    Sensor, // The band of the sensor, see `SensorModel`
+}
+
+impl MagnitudeBand {
+    /// Band written in a csv `band` field, `None` when it is not a known band
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "v" => Some(MagnitudeBand::V),
+            "sensor" => Some(MagnitudeBand::Sensor),
+            _ => None,
+        }
+    }
+
+    /// Code of the band in a binary catalog record
+    pub fn code(self) -> u32 {
+        match self {
+            MagnitudeBand::V => 0,
+            MagnitudeBand::Sensor => 1,
+        }
+    }
+}
+
+/// Relative spectral response of a pass band, sampled at increasing wavelengths
+#[derive(Clone, Debug)]
+pub struct ResponseCurve {
+    samples: Vec<(f64, f64)>, // (wavelength in nm, relative response)
+}
+
+impl ResponseCurve {
+    /// Builds a response curve from `(wavelength nm, response)` samples.
+    /// Needs at least two samples with increasing wavelengths and non-negative responses.
+    pub fn new(samples: Vec<(f64, f64)>) -> Result<Self, SatSightError> {
+        if samples.len() < 2 {
+            return Err(SatSightError::InvalidResponseCurve("at least two samples are needed".to_string()));
+        }
+        if samples.iter().any(|(wavelength, response)| !wavelength.is_finite() || !response.is_finite()) {
+            return Err(SatSightError::InvalidResponseCurve("samples have to be finite".to_string()));
+        }
+        if samples.windows(2).any(|pair| pair[1].0 <= pair[0].0) {
+            return Err(SatSightError::InvalidResponseCurve("wavelengths have to increase".to_string()));
+        }
+        if samples.iter().any(|(wavelength, response)| *wavelength <= 0.0 || *response < 0.0) {
+            return Err(SatSightError::InvalidResponseCurve("wavelengths have to be positive and responses non-negative".to_string()));
+        }
+        if samples.iter().all(|(_, response)| *response == 0.0) {
+            return Err(SatSightError::InvalidResponseCurve("response is zero everywhere".to_string()));
+        }
+        Ok(ResponseCurve { samples })
+    }
+
+    /// Johnson V pass band (Bessell 1990, PASP 102, 1181)
+    pub fn johnson_v() -> Self {
+        ResponseCurve {
+            samples: vec![
+                (470.0, 0.000), (480.0, 0.030), (490.0, 0.163), (500.0, 0.458), (510.0, 0.780),
+                (520.0, 0.967), (530.0, 1.000), (540.0, 0.973), (550.0, 0.898), (560.0, 0.792),
+                (570.0, 0.684), (580.0, 0.574), (590.0, 0.461), (600.0, 0.359), (610.0, 0.270),
+                (620.0, 0.197), (630.0, 0.135), (640.0, 0.081), (650.0, 0.045), (660.0, 0.025),
+                (670.0, 0.017), (680.0, 0.013), (690.0, 0.009), (700.0, 0.000),
+            ],
+        }
+    }
+
+    /// Quantum efficiency of a typical front illuminated CMOS sensor without an IR cut filter
+    pub fn silicon_cmos() -> Self {
+        ResponseCurve {
+            samples: vec![
+                (300.0, 0.00), (350.0, 0.15), (400.0, 0.35), (450.0, 0.50), (500.0, 0.60),
+                (550.0, 0.65), (600.0, 0.63), (650.0, 0.58), (700.0, 0.52), (750.0, 0.45),
+                (800.0, 0.38), (850.0, 0.30), (900.0, 0.22), (950.0, 0.13), (1000.0, 0.06),
+                (1050.0, 0.02), (1100.0, 0.00),
+            ],
+        }
+    }
+
+    /// Response at a wavelength (nm), linearly interpolated and zero outside the samples
+    pub fn response(&self, wavelength: f64) -> f64 {
+        let (first, last) = (self.samples[0].0, self.samples[self.samples.len() - 1].0);
+        if !(first..=last).contains(&wavelength) {
+            return 0.0;
+        }
+        let index = self.samples.partition_point(|(sample, _)| *sample < wavelength).max(1);
+        let (w0, r0) = self.samples[index - 1];
+        let (w1, r1) = self.samples[index];
+        r0 + (r1 - r0) * (wavelength - w0) / (w1 - w0)
+    }
+
+    /// Relative photon flux of a black body at `temperature` (K) through the band
+    fn photon_flux(&self, temperature: f64) -> f64 {
+        let (start, end) = (self.samples[0].0, self.samples[self.samples.len() - 1].0);
+        let steps = 200;
+        let step = (end - start) / steps as f64;
+        (0..=steps)
+            .map(|i| {
+                let wavelength = start + step * i as f64;
+                // Photons per unit wavelength go as B_lambda * lambda = lambda^-4 / (e^(hc/lambda k T) - 1)
+                let photons = wavelength.powi(-4) / (SECOND_RADIATION_CONSTANT / (wavelength * temperature)).exp_m1();
+                let weight = if i == 0 || i == steps { 0.5 } else { 1.0 };
+                weight * photons * self.response(wavelength)
+            })
+            .sum::<f64>()
+            * step
+    }
+}
+
+/// Reads a response curve from a csv file with `wavelength` (nm) and `response` columns
+pub fn parse_response_curve_file(file: File) -> Result<ResponseCurve, SatSightError> {
+    let mut reader = Reader::from_reader(file);
+    let headers = reader.headers()?.clone();
+    let wavelength_column = require_column(&headers, "wavelength")?;
+    let response_column = require_column(&headers, "response")?;
+
+    let mut samples: Vec<(f64, f64)> = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+        let line = record.position().map_or(0, |position| position.line());
+        let wavelength = parse_csv_field(&record, wavelength_column, "wavelength")?
+            .ok_or_else(|| SatSightError::parse(line, "wavelength", "missing value"))?;
+        let response = parse_csv_field(&record, response_column, "response")?
+            .ok_or_else(|| SatSightError::parse(line, "response", "missing value"))?;
+        samples.push((wavelength, response));
+    }
+    ResponseCurve::new(samples)
+}
+
+// ==== Colour =====================================================================================
+// Effective temperature from B-V and intrinsic B-V from the spectral type
+// =================================================================================================
+
+/// Effective temperature (K) of a star with colour `b_v` (Ballesteros 2012, EPL 97, 34008)
+pub fn b_v_to_temperature(b_v: f32) -> f64 {
+    let b_v = b_v as f64;
+    4600.0 * (1.0 / (0.92 * b_v + 1.7) + 1.0 / (0.92 * b_v + 0.62))
+}
+
+/// Intrinsic B-V of main sequence stars at subclasses 0 and 5 of each class (Schmidt-Kaler 1982)
+const SPECTRAL_B_V: [(char, f32, f32); 7] = [
+    ('O', -0.33, -0.33),
+    ('B', -0.30, -0.17),
+    ('A', -0.02, 0.15),
+    ('F', 0.30, 0.44),
+    ('G', 0.58, 0.68),
+    ('K', 0.81, 1.15),
+    ('M', 1.40, 1.64),
+];
+
+/// Estimates B-V from a spectral type like `K0III`, `B9.5V` or `gG9`.
+///
+/// The first upper case class letter is used with its subclass (5 when missing). The
+/// luminosity class is ignored, so giants come out slightly too blue. Returns `None` for
+/// types without an OBAFGKM class, e.g. carbon stars.
+pub fn spectral_type_to_b_v(sp_type: &str) -> Option<f32> {
+    let (start, class) = sp_type
+        .char_indices()
+        .find(|(_, c)| SPECTRAL_B_V.iter().any(|(class, _, _)| class == c))?;
+    let index = SPECTRAL_B_V.iter().position(|(c, _, _)| *c == class)?;
+
+    let digits: String = sp_type[start + 1..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    let subclass = digits.parse::<f32>().unwrap_or(5.0).clamp(0.0, 9.9);
+
+    // Interpolate from subclass 0 to 5, then on to subclass 0 of the next class
+    let (_, b_v_0, b_v_5) = SPECTRAL_B_V[index];
+    if subclass <= 5.0 {
+        return Some(b_v_0 + (b_v_5 - b_v_0) * subclass / 5.0);
+    }
+    let next = SPECTRAL_B_V.get(index + 1).map_or(b_v_5 + (b_v_5 - b_v_0), |(_, b_v, _)| *b_v);
+    Some(b_v_5 + (next - b_v_5) * (subclass - 5.0) / 5.0)
+}
+
+// ==== Sensor Model ===============================================================================
+// V to sensor band conversion and the uses of sensor magnitudes
+// =================================================================================================
+
+/// Converts Johnson V magnitudes into the band of a sensor.
+///
+/// The colour term is precomputed on a B-V grid when the model is built, so converting a
+/// large catalog only costs an interpolation per star.
+#[derive(Clone, Debug)]
+pub struct SensorModel {
+    color_terms: Vec<f32>, // Sensor - V at B-V = B_V_MIN + i * B_V_STEP
+}
+
+impl Default for SensorModel {
+    /// Model of a typical CMOS sensor, see `ResponseCurve::silicon_cmos`
+    fn default() -> Self {
+        SensorModel::new(&ResponseCurve::silicon_cmos())
+    }
+}
+
+impl SensorModel {
+    pub fn new(response: &ResponseCurve) -> Self {
+        let v_band = ResponseCurve::johnson_v();
+        let ratio = |b_v: f32| {
+            let temperature = b_v_to_temperature(b_v);
+            response.photon_flux(temperature) / v_band.photon_flux(temperature)
+        };
+        let reference = ratio(0.0);
+
+        let count = ((B_V_MAX - B_V_MIN) / B_V_STEP).round() as usize + 1;
+        let color_terms = (0..count)
+            .map(|i| (-2.5 * (ratio(B_V_MIN + B_V_STEP * i as f32) / reference).log10()) as f32)
+            .collect();
+        SensorModel { color_terms }
+    }
+
+    /// Sensor magnitude minus V for a star of colour `b_v`, clamped to the modelled range
+    pub fn color_term(&self, b_v: f32) -> f32 {
+        let position = ((b_v.clamp(B_V_MIN, B_V_MAX) - B_V_MIN) / B_V_STEP).max(0.0);
+        let index = (position.floor() as usize).min(self.color_terms.len() - 2);
+        let fraction = position - index as f32;
+        self.color_terms[index] + (self.color_terms[index + 1] - self.color_terms[index]) * fraction
+    }
+
+    /// Predicted sensor magnitude from V and the colour, or the spectral type when the colour
+    /// is unknown. Stars with neither are assumed to be white and keep their V magnitude.
+    pub fn sensor_magnitude(&self, vmag: f32, b_v: Option<f32>, sp_type: &str) -> f32 {
+        match b_v.or_else(|| spectral_type_to_b_v(sp_type)) {
+            Some(b_v) => vmag + self.color_term(b_v),
+            None => vmag,
+        }
+    }
+}
+
+/// Magnitude of a measured flux (e.g. the summed pixel values of a detected star) given the
+/// flux of a magnitude 0 star on the same sensor
+pub fn instrument_magnitude(flux: f64, zero_point_flux: f64) -> f32 {
+    (-2.5 * (flux / zero_point_flux).log10()) as f32
+}
+
+/// True when a measured magnitude agrees with the star's predicted sensor magnitude.
+/// A star whose magnitude is still Johnson V is never consistent, build it with
+/// `to_sensor_star` or `parse_sensor_star_file_with_schema` first.
+pub fn magnitude_consistent(star: &Star, measured_mag: f32, tolerance: f32) -> bool {
+    star.band == MagnitudeBand::Sensor && (star.mag - measured_mag).abs() <= tolerance
+}
+
+/// Pixel value for rendering a star of sensor magnitude `mag`. Brightness is linear in flux,
+/// `saturation_mag` and brighter stars are 255 and stars fainter than `limiting_mag` are 0.
+pub fn magnitude_to_intensity(mag: f32, saturation_mag: f32, limiting_mag: f32) -> u8 {
+    if mag > limiting_mag {
+        return 0;
+    }
+    let relative_flux = 10_f32.powf(-0.4 * (mag - saturation_mag));
+    (255.0 * relative_flux).round().clamp(1.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn red_stars_are_brighter_on_the_sensor() {
+        let model = SensorModel::default();
+        assert!(model.color_term(0.0).abs() < 1e-6);
+        // K and M stars gain a few tenths of a magnitude, more the redder they are
+        assert!(model.color_term(0.81) < -0.1 && model.color_term(1.5) < -0.3);
+        let terms: Vec<f32> = (0..=20).map(|i| model.color_term(i as f32 * 0.1)).collect();
+        assert!(terms.windows(2).all(|pair| pair[1] < pair[0]));
+
+        assert_eq!(model.sensor_magnitude(5.0, Some(1.5), "A0V"), 5.0 + model.color_term(1.5));
+        assert_eq!(model.sensor_magnitude(5.0, None, "K0III"), 5.0 + model.color_term(0.81));
+        assert_eq!(model.sensor_magnitude(5.0, None, ""), 5.0);
+    }
+
+    #[test]
+    fn spectral_types_give_their_colour() {
+        let b_v = |sp_type: &str| spectral_type_to_b_v(sp_type).unwrap();
+        assert!((b_v("K0III") - 0.81).abs() < 1e-6);
+        assert!((b_v("A0V") + 0.02).abs() < 1e-6);
+        // Decimal subclasses interpolate towards the next class, prefixes like `g` are skipped
+        assert!((b_v("B9.5V") + 0.035).abs() < 1e-6);
+        assert!((b_v("gG9") - 0.784).abs() < 1e-6);
+        // A missing subclass is taken as 5
+        assert!((b_v("K") - 1.15).abs() < 1e-6);
+        assert_eq!(spectral_type_to_b_v("C5,4"), None);
+        assert_eq!(spectral_type_to_b_v(""), None);
+    }
+
+    #[test]
+    fn response_curves_are_validated() {
+        let invalid = |samples: Vec<(f64, f64)>| matches!(ResponseCurve::new(samples), Err(SatSightError::InvalidResponseCurve(_)));
+        assert!(invalid(vec![(500.0, 1.0)]));
+        assert!(invalid(vec![(500.0, 1.0), (500.0, 0.5)]));
+        assert!(invalid(vec![(600.0, 1.0), (500.0, 0.5)]));
+        assert!(invalid(vec![(500.0, 1.0), (600.0, -0.1)]));
+        assert!(invalid(vec![(-10.0, 1.0), (600.0, 0.5)]));
+        assert!(invalid(vec![(500.0, f64::NAN), (600.0, 0.5)]));
+        assert!(invalid(vec![(500.0, 0.0), (600.0, 0.0)]));
+
+        let curve = ResponseCurve::new(vec![(500.0, 0.0), (600.0, 1.0)]).unwrap();
+        assert_eq!((curve.response(550.0), curve.response(600.0)), (0.5, 1.0));
+        assert_eq!((curve.response(499.0), curve.response(601.0)), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,165 @@
+//! Precomputed pin-prick lookup tables.
+//!
+//! The first blur and prick method in the README projects the catalog once
+//! for a grid of attitudes and stores the pixels, so matching an image is a
+//! pass over memory with no trigonometry. The tables are large, so they can
+//! opt in to storing attitudes and pixels as `f32`: about 0.01 pixel and
+//! 0.1 arcsecond of rounding, far below the step of the attitude grid. The
+//! rest of the astrometric path stays in `f64`.
+
+use image::GrayImage;
+use nalgebra::{Quaternion, Vector3};
+
+use super::attitude::Attitude;
+use super::error::SatSightError;
+use super::projection::{Pixel, Projection};
+use super::sky_index::SkyIndex;
+use super::{project_stars, sample_image, Star};
+
+/// Floating point type the values of a table are stored as
+pub trait TableFloat: Copy {
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl TableFloat for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl TableFloat for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+/// The stars seen from one attitude of the table
+#[derive(Clone, Debug)]
+struct TableEntry<F: TableFloat> {
+    quaternion: [F; 4],    // Attitude quaternion, scalar first
+    pixels: Vec<(F, F)>,   // Sub-pixel positions of the stars on the image
+}
+
+/// Star pixels precomputed for a list of attitudes, stored as `F`
+#[derive(Clone, Debug)]
+pub struct PinPrickTable<F: TableFloat = f64> {
+    entries: Vec<TableEntry<F>>,
+}
+
+/// A table stored in single precision, half the memory of `PinPrickTable`
+pub type CompactPinPrickTable = PinPrickTable<f32>;
+
+impl<F: TableFloat> PinPrickTable<F> {
+    /// Projects the stars of `index` for every attitude
+    pub fn build(index: &SkyIndex, attitudes: &[Attitude], projection: &dyn Projection) -> Result<Self, SatSightError> {
+        let entries = attitudes
+            .iter()
+            .map(|attitude| {
+                let boresight = attitude.rotate(&Vector3::z());
+                let stars: Vec<Star> = index.cone(&boresight, projection.field_radius(0.0))?.into_iter().cloned().collect();
+                let pixels = project_stars(&stars, attitude, projection)
+                    .into_iter()
+                    .map(|projected| (F::from_f64(projected.pixel.0), F::from_f64(projected.pixel.1)))
+                    .collect();
+                Ok(TableEntry {
+                    quaternion: attitude.to_scalar_first().map(F::from_f64),
+                    pixels,
+                })
+            })
+            .collect::<Result<Vec<_>, SatSightError>>()?;
+        Ok(PinPrickTable { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Attitude of an entry, renormalised after the rounding of the storage type.
+    /// `None` when `entry` is out of range.
+    pub fn attitude(&self, entry: usize) -> Option<Attitude> {
+        let [w, x, y, z] = self.entries.get(entry)?.quaternion.map(F::to_f64);
+        // Stored quaternions come from unit attitudes, rounding can not bring them near zero
+        Attitude::from_quaternion(Quaternion::new(w, x, y, z)).ok()
+    }
+
+    /// Pixels of the stars seen from an entry's attitude, `None` when `entry` is out of range
+    pub fn pixels(&self, entry: usize) -> Option<impl Iterator<Item = Pixel> + '_> {
+        Some(self.entries.get(entry)?.pixels.iter().map(|(x, y)| (x.to_f64(), y.to_f64())))
+    }
+
+    /// Goodness of an entry for a blurred image, see `pin_prick_image`.
+    /// `None` when `entry` is out of range.
+    pub fn score(&self, image: &GrayImage, entry: usize) -> Option<f64> {
+        Some(self.pixels(entry)?.filter_map(|pixel| sample_image(image, pixel)).sum())
+    }
+
+    /// The attitude with the highest goodness for a blurred image and its score
+    pub fn best_match(&self, image: &GrayImage) -> Option<(Attitude, f64)> {
+        (0..self.len())
+            .filter_map(|entry| Some((self.attitude(entry)?, self.score(image, entry)?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat_sight::camera::CameraModel;
+    use crate::sat_sight::photometry::MagnitudeBand;
+    use crate::sat_sight::{render_stars, StarId};
+
+    /// Stars on a spiral within a few degrees of the galactic centre
+    fn stars() -> Vec<Star> {
+        (1..=60)
+            .map(|hr| {
+                let (radius, angle) = (0.1 * hr as f64, 2.4 * hr as f64);
+                Star {
+                    hr,
+                    id: StarId::from_hr(hr),
+                    lat: radius * angle.sin(),
+                    lon: (radius * angle.cos()).rem_euclid(360.0),
+                    mag: 2.0 + (hr % 5) as f32,
+                    band: MagnitudeBand::Sensor,
+                    fingure_print: 0.0,
+                    flags: 0,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn single_and_double_precision_tables_find_the_same_attitude() {
+        let camera = CameraModel::default();
+        let truth = Attitude::from_ra_dec_roll(0.5, -0.25, 20.0);
+        let mut image = GrayImage::new(camera.width, camera.height);
+        render_stars(&mut image, &stars(), &truth, &camera, 1.0, 7.5).unwrap();
+        let image = image::imageops::blur(&image, 2.0);
+
+        // A quarter degree grid around the true attitude, which is one of its points
+        let attitudes: Vec<Attitude> = (-4..=4)
+            .flat_map(|i| (-4..=4).map(move |j| Attitude::from_ra_dec_roll(0.5 + 0.25 * i as f64, -0.25 + 0.25 * j as f64, 20.0)))
+            .collect();
+        let index = SkyIndex::new(stars(), 5.0).unwrap();
+        let table = PinPrickTable::<f64>::build(&index, &attitudes, &camera).unwrap();
+        let compact = CompactPinPrickTable::build(&index, &attitudes, &camera).unwrap();
+
+        let (attitude, score) = table.best_match(&image).unwrap();
+        let (compact_attitude, compact_score) = compact.best_match(&image).unwrap();
+        assert!(attitude.angle_to(&truth) < 1e-9);
+        assert!(compact_attitude.angle_to(&truth) < 1e-6);
+        assert!((compact_score / score - 1.0).abs() < 1e-4);
+    }
+}
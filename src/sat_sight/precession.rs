@@ -0,0 +1,225 @@
+//! Precession and nutation of the equatorial frame.
+//!
+//! Catalog positions and attitudes are kept in the J2000 equatorial frame.
+//! Some flight software expects the mean equator and equinox of date (J2000
+//! plus precession) or the true equator and equinox of date (plus nutation).
+//! Precession uses the IAU 1976 angles, nutation the IAU 1980 series truncated
+//! to the terms of 0.0003" and larger (Meeus, Astronomical Algorithms, table 22.A),
+//! which keeps the error of either well below 0.01" over this century.
+//! The ~0.02" frame bias between ICRF and the J2000 dynamical frame is ignored.
+//!
+//! Every function takes the Julian date in TT.
+
+use nalgebra::{Matrix3, Quaternion};
+
+use super::astrometry::{julian_centuries, ARCSEC};
+use super::frames::{rot_x, rot_y, rot_z};
+use super::rotation_matrix_to_quaternion;
+
+/// IAU 1980 nutation series: multiples of D, M, M', F, Omega followed by the
+/// longitude (sin) and obliquity (cos) coefficients and their rates, in 0.0001"
+#[rustfmt::skip]
+const NUTATION_TERMS: [([i8; 5], f64, f64, f64, f64); 63] = [
+    ([ 0,  0,  0,  0,  1], -171996.0, -174.2, 92025.0,  8.9),
+    ([-2,  0,  0,  2,  2],  -13187.0,   -1.6,  5736.0, -3.1),
+    ([ 0,  0,  0,  2,  2],   -2274.0,   -0.2,   977.0, -0.5),
+    ([ 0,  0,  0,  0,  2],    2062.0,    0.2,  -895.0,  0.5),
+    ([ 0,  1,  0,  0,  0],    1426.0,   -3.4,    54.0, -0.1),
+    ([ 0,  0,  1,  0,  0],     712.0,    0.1,    -7.0,  0.0),
+    ([-2,  1,  0,  2,  2],    -517.0,    1.2,   224.0, -0.6),
+    ([ 0,  0,  0,  2,  1],    -386.0,   -0.4,   200.0,  0.0),
+    ([ 0,  0,  1,  2,  2],    -301.0,    0.0,   129.0, -0.1),
+    ([-2, -1,  0,  2,  2],     217.0,   -0.5,   -95.0,  0.3),
+    ([-2,  0,  1,  0,  0],    -158.0,    0.0,     0.0,  0.0),
+    ([-2,  0,  0,  2,  1],     129.0,    0.1,   -70.0,  0.0),
+    ([ 0,  0, -1,  2,  2],     123.0,    0.0,   -53.0,  0.0),
+    ([ 2,  0,  0,  0,  0],      63.0,    0.0,     0.0,  0.0),
+    ([ 0,  0,  1,  0,  1],      63.0,    0.1,   -33.0,  0.0),
+    ([ 2,  0, -1,  2,  2],     -59.0,    0.0,    26.0,  0.0),
+    ([ 0,  0, -1,  0,  1],     -58.0,   -0.1,    32.0,  0.0),
+    ([ 0,  0,  1,  2,  1],     -51.0,    0.0,    27.0,  0.0),
+    ([-2,  0,  2,  0,  0],      48.0,    0.0,     0.0,  0.0),
+    ([ 0,  0, -2,  2,  1],      46.0,    0.0,   -24.0,  0.0),
+    ([ 2,  0,  0,  2,  2],     -38.0,    0.0,    16.0,  0.0),
+    ([ 0,  0,  2,  2,  2],     -31.0,    0.0,    13.0,  0.0),
+    ([ 0,  0,  2,  0,  0],      29.0,    0.0,     0.0,  0.0),
+    ([-2,  0,  1,  2,  2],      29.0,    0.0,   -12.0,  0.0),
+    ([ 0,  0,  0,  2,  0],      26.0,    0.0,     0.0,  0.0),
+    ([-2,  0,  0,  2,  0],     -22.0,    0.0,     0.0,  0.0),
+    ([ 0,  0, -1,  2,  1],      21.0,    0.0,   -10.0,  0.0),
+    ([ 0,  2,  0,  0,  0],      17.0,   -0.1,     0.0,  0.0),
+    ([ 2,  0, -1,  0,  1],      16.0,    0.0,    -8.0,  0.0),
+    ([-2,  2,  0,  2,  2],     -16.0,    0.1,     7.0,  0.0),
+    ([ 0,  1,  0,  0,  1],     -15.0,    0.0,     9.0,  0.0),
+    ([-2,  0,  1,  0,  1],     -13.0,    0.0,     7.0,  0.0),
+    ([ 0, -1,  0,  0,  1],     -12.0,    0.0,     6.0,  0.0),
+    ([ 0,  0,  2, -2,  0],      11.0,    0.0,     0.0,  0.0),
+    ([ 2,  0, -1,  2,  1],     -10.0,    0.0,     5.0,  0.0),
+    ([ 2,  0,  1,  2,  2],      -8.0,    0.0,     3.0,  0.0),
+    ([ 0,  1,  0,  2,  2],       7.0,    0.0,    -3.0,  0.0),
+    ([-2,  1,  1,  0,  0],      -7.0,    0.0,     0.0,  0.0),
+    ([ 0, -1,  0,  2,  2],      -7.0,    0.0,     3.0,  0.0),
+    ([ 2,  0,  0,  2,  1],      -7.0,    0.0,     3.0,  0.0),
+    ([ 2,  0,  1,  0,  0],       6.0,    0.0,     0.0,  0.0),
+    ([-2,  0,  2,  2,  2],       6.0,    0.0,    -3.0,  0.0),
+    ([-2,  0,  1,  2,  1],       6.0,    0.0,    -3.0,  0.0),
+    ([ 2,  0, -2,  0,  1],      -6.0,    0.0,     3.0,  0.0),
+    ([ 2,  0,  0,  0,  1],      -6.0,    0.0,     3.0,  0.0),
+    ([ 0, -1,  1,  0,  0],       5.0,    0.0,     0.0,  0.0),
+    ([-2, -1,  0,  2,  1],      -5.0,    0.0,     3.0,  0.0),
+    ([-2,  0,  0,  0,  1],      -5.0,    0.0,     3.0,  0.0),
+    ([ 0,  0,  2,  2,  1],      -5.0,    0.0,     3.0,  0.0),
+    ([-2,  0,  2,  0,  1],       4.0,    0.0,     0.0,  0.0),
+    ([-2,  1,  0,  2,  1],       4.0,    0.0,     0.0,  0.0),
+    ([ 0,  0,  1, -2,  0],       4.0,    0.0,     0.0,  0.0),
+    ([-1,  0,  1,  0,  0],      -4.0,    0.0,     0.0,  0.0),
+    ([-2,  1,  0,  0,  0],      -4.0,    0.0,     0.0,  0.0),
+    ([ 1,  0,  0,  0,  0],      -4.0,    0.0,     0.0,  0.0),
+    ([ 0,  0,  1,  2,  0],       3.0,    0.0,     0.0,  0.0),
+    ([ 0,  0, -2,  2,  2],      -3.0,    0.0,     0.0,  0.0),
+    ([-1, -1,  1,  0,  0],      -3.0,    0.0,     0.0,  0.0),
+    ([ 0,  1,  1,  0,  0],      -3.0,    0.0,     0.0,  0.0),
+    ([ 0, -1,  1,  2,  2],      -3.0,    0.0,     0.0,  0.0),
+    ([ 2, -1, -1,  2,  2],      -3.0,    0.0,     0.0,  0.0),
+    ([ 0,  0,  3,  2,  2],      -3.0,    0.0,     0.0,  0.0),
+    ([ 2, -1,  0,  2,  2],      -3.0,    0.0,     0.0,  0.0),
+];
+
+// ==== Precession =================================================================================
+// IAU 1976 precession from the J2000 mean equator and equinox
+// =================================================================================================
+
+/// Returns the IAU 1976 precession angles (zeta, z, theta) from J2000 in radians
+pub fn precession_angles(jd: f64) -> (f64, f64, f64) {
+    let t = julian_centuries(jd);
+    let zeta = (2306.2181 + (0.30188 + 0.017998 * t) * t) * t;
+    let z = (2306.2181 + (1.09468 + 0.018203 * t) * t) * t;
+    let theta = (2004.3109 - (0.42665 + 0.041833 * t) * t) * t;
+    (zeta * ARCSEC, z * ARCSEC, theta * ARCSEC)
+}
+
+/// Matrix taking J2000 equatorial components to mean of date components
+pub fn precession_matrix(jd: f64) -> Matrix3<f64> {
+    let (zeta, z, theta) = precession_angles(jd);
+    rot_z(-z) * rot_y(theta) * rot_z(-zeta)
+}
+
+// ==== Nutation ===================================================================================
+// IAU 1980 nutation from the mean to the true equator and equinox of date
+// =================================================================================================
+
+/// Mean obliquity of the ecliptic of date (IAU 1980) in radians
+pub fn mean_obliquity(jd: f64) -> f64 {
+    let t = julian_centuries(jd);
+    (84381.448 + (-46.8150 + (-0.00059 + 0.001813 * t) * t) * t) * ARCSEC
+}
+
+/// Returns the nutation in longitude and in obliquity (delta psi, delta epsilon) in radians
+pub fn nutation(jd: f64) -> (f64, f64) {
+    let t = julian_centuries(jd);
+
+    // Fundamental arguments in degrees: mean elongation of the Moon from the Sun, mean anomaly
+    // of the Sun, mean anomaly of the Moon, Moon's argument of latitude, longitude of the
+    // ascending node of the Moon's mean orbit
+    let d = 297.85036 + (445267.111480 + (-0.0019142 + t / 189474.0) * t) * t;
+    let m = 357.52772 + (35999.050340 + (-0.0001603 - t / 300000.0) * t) * t;
+    let m_moon = 134.96298 + (477198.867398 + (0.0086972 + t / 56250.0) * t) * t;
+    let f = 93.27191 + (483202.017538 + (-0.0036825 + t / 327270.0) * t) * t;
+    let omega = 125.04452 + (-1934.136261 + (0.0020708 + t / 450000.0) * t) * t;
+    let arguments = [d, m, m_moon, f, omega].map(f64::to_radians);
+
+    let (mut dpsi, mut deps) = (0.0, 0.0);
+    for (multiples, psi, psi_rate, eps, eps_rate) in NUTATION_TERMS.iter() {
+        let argument: f64 = multiples
+            .iter()
+            .zip(arguments.iter())
+            .map(|(multiple, angle)| *multiple as f64 * angle)
+            .sum();
+        dpsi += (psi + psi_rate * t) * argument.sin();
+        deps += (eps + eps_rate * t) * argument.cos();
+    }
+    (dpsi * 1e-4 * ARCSEC, deps * 1e-4 * ARCSEC)
+}
+
+/// Matrix taking mean of date components to true of date components
+pub fn nutation_matrix(jd: f64) -> Matrix3<f64> {
+    let (dpsi, deps) = nutation(jd);
+    let eps0 = mean_obliquity(jd);
+    rot_x(-(eps0 + deps)) * rot_z(-dpsi) * rot_x(eps0)
+}
+
+/// Matrix taking J2000 equatorial components to true of date components
+pub fn precession_nutation_matrix(jd: f64) -> Matrix3<f64> {
+    nutation_matrix(jd) * precession_matrix(jd)
+}
+
+// ==== Quaternions ================================================================================
+// The same rotations as quaternions for use with the quaternion math in `sat_sight`
+// =================================================================================================
+
+/// Quaternion taking J2000 equatorial vectors to mean of date vectors with `rotate_vectors`
+pub fn j2000_to_mean_of_date(jd: f64) -> Quaternion<f64> {
+    rotation_matrix_to_quaternion(&precession_matrix(jd))
+}
+
+/// Quaternion taking J2000 equatorial vectors to true of date vectors with `rotate_vectors`
+pub fn j2000_to_true_of_date(jd: f64) -> Quaternion<f64> {
+    rotation_matrix_to_quaternion(&precession_nutation_matrix(jd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat_sight::astrometry::julian_date;
+    use crate::sat_sight::frames::{lat_lon_to_unit_vector, unit_vector_to_lat_lon};
+
+    fn arcsec(radians: f64) -> f64 {
+        radians / ARCSEC
+    }
+
+    #[test]
+    fn nutation_matches_meeus_example_22a() {
+        // 1987 April 10, 0h TD: dpsi = -3.788", deps = +9.443"
+        let jd = julian_date(1987, 4, 10.0);
+        assert_eq!(jd, 2446895.5);
+
+        let (dpsi, deps) = nutation(jd);
+        assert!((arcsec(dpsi) + 3.788).abs() < 0.001, "dpsi = {}", arcsec(dpsi));
+        assert!((arcsec(deps) - 9.443).abs() < 0.001, "deps = {}", arcsec(deps));
+    }
+
+    #[test]
+    fn mean_obliquity_matches_meeus_example_22a() {
+        // 1987 April 10, 0h TD: epsilon_0 = 23d 26' 27.407"
+        let eps0 = arcsec(mean_obliquity(2446895.5));
+        let expected = 23.0 * 3600.0 + 26.0 * 60.0 + 27.407;
+        assert!((eps0 - expected).abs() < 0.001, "eps0 = {}", eps0);
+    }
+
+    #[test]
+    fn precession_matches_meeus_example_21b() {
+        // theta Persei at J2000 (proper motion already applied) precessed to 2028 November 13.19 TD
+        let ra0 = (2.0 + 44.0 / 60.0 + 12.975 / 3600.0) * 15.0;
+        let dec0 = 49.0 + 13.0 / 60.0 + 39.90 / 3600.0;
+        let expected_ra = (2.0 + 46.0 / 60.0 + 11.331 / 3600.0) * 15.0;
+        let expected_dec = 49.0 + 20.0 / 60.0 + 54.54 / 3600.0;
+
+        let v = precession_matrix(2462088.69) * lat_lon_to_unit_vector(dec0, ra0);
+        let (dec, ra) = unit_vector_to_lat_lon(&v).unwrap();
+
+        let ra_error = (ra - expected_ra) * 3600.0 * dec.to_radians().cos();
+        let dec_error = (dec - expected_dec) * 3600.0;
+        assert!(ra_error.abs() < 0.02, "ra error = {}\"", ra_error);
+        assert!(dec_error.abs() < 0.02, "dec error = {}\"", dec_error);
+    }
+
+    #[test]
+    fn quaternions_match_matrices() {
+        let jd = julian_date(2035, 6, 1.0);
+        let v = lat_lon_to_unit_vector(-35.0, 120.0);
+        let q = j2000_to_true_of_date(jd);
+        let rotated = q * Quaternion::new(0.0, v.x, v.y, v.z) * q.conjugate();
+        let expected = precession_nutation_matrix(jd) * v;
+        assert!((rotated.imag() - expected).norm() < 1e-12);
+    }
+}
@@ -0,0 +1,57 @@
+//! Optics independent projection between camera frame directions and pixels.
+//!
+//! The pipeline (`get_pix`, `pin_prick_image`, `render_stars` and the
+//! centroid to vector conversion) only talks to the optics through the
+//! `Projection` trait, so swapping a pinhole camera for a fisheye, or for a
+//! lookup table calibrated on the ground, does not touch the pipeline.
+//! `CameraModel` implements it for all of its `LensProjection`s.
+//!
+//! Directions are in the camera frame, +z along the boresight, and pixels
+//! are continuous (column, row) positions, see `camera`.
+
+use nalgebra::Vector3;
+
+use super::camera::CameraModel;
+
+/// Sub-pixel (column, row) position with (0, 0) at the top left corner of the image
+pub type Pixel = (f64, f64);
+
+/// Maps camera frame directions onto an image and back
+pub trait Projection {
+    /// Pixel a camera frame direction (any length) lands on, `None` when the optics can not see
+    /// the direction. The pixel does not have to be on the image, see `contains`.
+    fn project(&self, direction: &Vector3<f64>) -> Option<Pixel>;
+
+    /// Unit camera frame direction seen at a pixel
+    fn unproject(&self, pixel: Pixel) -> Vector3<f64>;
+
+    /// True when a pixel lies on the image widened by `margin` pixels on every side
+    fn contains_with_margin(&self, pixel: Pixel, margin: f64) -> bool;
+
+    /// Angle from the boresight (deg) that no direction landing on the image widened by
+    /// `margin` pixels exceeds, the cone a spatial index has to search
+    fn field_radius(&self, margin: f64) -> f64;
+
+    /// True when a pixel lies on the image
+    fn contains(&self, pixel: Pixel) -> bool {
+        self.contains_with_margin(pixel, 0.0)
+    }
+}
+
+impl Projection for CameraModel {
+    fn project(&self, direction: &Vector3<f64>) -> Option<Pixel> {
+        CameraModel::project(self, direction)
+    }
+
+    fn unproject(&self, pixel: Pixel) -> Vector3<f64> {
+        CameraModel::unproject(self, pixel)
+    }
+
+    fn contains_with_margin(&self, pixel: Pixel, margin: f64) -> bool {
+        CameraModel::contains_with_margin(self, pixel, margin)
+    }
+
+    fn field_radius(&self, margin: f64) -> f64 {
+        CameraModel::field_radius(self, margin)
+    }
+}
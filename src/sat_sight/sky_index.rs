@@ -0,0 +1,248 @@
+//! Spatial index of the sky for fast visible star queries.
+//!
+//! Scanning the whole catalog for every candidate attitude is what makes the
+//! all-sky pin-prick sweep slow. The index splits the sphere into latitude
+//! bands of equal height, and each band into longitude cells about as wide as
+//! they are high, so all cells cover roughly the same area. A cone query only
+//! visits the cells overlapping the cone and then checks the stars in them
+//! exactly against their unit vectors, so its cost follows the number of
+//! stars returned rather than the size of the catalog.
+
+use nalgebra::Vector3;
+
+use super::attitude::Attitude;
+use super::error::SatSightError;
+use super::frames::{lat_lon_to_unit_vector, unit_vector_to_lat_lon};
+use super::projection::Projection;
+use super::{in_frustum, Star};
+
+/// Stars sorted into a grid of latitude bands and longitude cells
+#[derive(Clone, Debug)]
+pub struct SkyIndex {
+    cell_size: f64,                // Height of a band and about the width of a cell (deg)
+    band_starts: Vec<usize>,       // Index of the first cell of each band, plus the total cell count
+    cell_starts: Vec<usize>,       // Index into `entries` of the first star of each cell, plus the total
+    entries: Vec<usize>,           // Star indices grouped by cell
+    stars: Vec<Star>,
+    directions: Vec<Vector3<f64>>, // Unit vector of each star
+}
+
+impl SkyIndex {
+    /// Builds the index over `stars` with cells of about `cell_size` degrees.
+    /// Cells about the size of the field of view or a bit smaller work well.
+    pub fn new(stars: Vec<Star>, cell_size: f64) -> Result<Self, SatSightError> {
+        if !(cell_size > 0.0 && cell_size <= 180.0) {
+            return Err(SatSightError::degenerate("cell size has to be between 0 and 180 degrees"));
+        }
+
+        let band_count = (180.0 / cell_size).ceil() as usize;
+        let mut band_starts = Vec::with_capacity(band_count + 1);
+        let mut cell_count = 0;
+        for band in 0..band_count {
+            band_starts.push(cell_count);
+            // Size the band by its edge nearest the equator, where it is widest
+            let (bottom, top) = (band as f64 * cell_size - 90.0, ((band + 1) as f64 * cell_size - 90.0).min(90.0));
+            let widest = if bottom <= 0.0 && top >= 0.0 { 0.0 } else { bottom.abs().min(top.abs()) };
+            cell_count += ((360.0 * widest.to_radians().cos() / cell_size).ceil() as usize).max(1);
+        }
+        band_starts.push(cell_count);
+
+        let mut index = SkyIndex {
+            cell_size,
+            band_starts,
+            cell_starts: Vec::new(),
+            entries: Vec::new(),
+            directions: stars.iter().map(|star| lat_lon_to_unit_vector(star.lat, star.lon)).collect(),
+            stars,
+        };
+
+        // Counting sort of the stars by cell
+        let cells: Vec<usize> = index.stars.iter().map(|star| index.cell_of(star.lat, star.lon)).collect();
+        let mut cell_starts = vec![0; cell_count + 1];
+        for &cell in &cells {
+            cell_starts[cell + 1] += 1;
+        }
+        for cell in 0..cell_count {
+            cell_starts[cell + 1] += cell_starts[cell];
+        }
+        let mut next = cell_starts.clone();
+        let mut entries = vec![0; cells.len()];
+        for (star, &cell) in cells.iter().enumerate() {
+            entries[next[cell]] = star;
+            next[cell] += 1;
+        }
+        index.cell_starts = cell_starts;
+        index.entries = entries;
+        Ok(index)
+    }
+
+    /// All the stars in the index, in the order they were given
+    pub fn stars(&self) -> &[Star] {
+        &self.stars
+    }
+
+    fn band_count(&self) -> usize {
+        self.band_starts.len() - 1
+    }
+
+    fn band_of(&self, lat: f64) -> usize {
+        (((lat + 90.0) / self.cell_size).floor().max(0.0) as usize).min(self.band_count() - 1)
+    }
+
+    fn cell_of(&self, lat: f64, lon: f64) -> usize {
+        let band = self.band_of(lat);
+        let cells = self.band_starts[band + 1] - self.band_starts[band];
+        let cell = (lon.rem_euclid(360.0) / 360.0 * cells as f64).floor() as usize;
+        self.band_starts[band] + cell.min(cells - 1)
+    }
+
+    /// Stars within `radius` degrees of `center`, a direction in the frame of the star positions
+    pub fn cone(&self, center: &Vector3<f64>, radius: f64) -> Result<Vec<&Star>, SatSightError> {
+        Ok(self.cone_indices(center, radius)?.into_iter().map(|star| &self.stars[star]).collect())
+    }
+
+    fn cone_indices(&self, center: &Vector3<f64>, radius: f64) -> Result<Vec<usize>, SatSightError> {
+        let (lat, lon) = unit_vector_to_lat_lon(center)?;
+        let center = center.normalize();
+        let cos_radius = radius.min(180.0).to_radians().cos();
+
+        // Longitude half width of the cone, all longitudes when it reaches over a pole
+        let all_longitudes = radius >= 90.0 || lat + radius >= 90.0 || lat - radius <= -90.0;
+        let half_width = if all_longitudes {
+            180.0
+        } else {
+            (radius.to_radians().sin() / lat.to_radians().cos()).min(1.0).asin().to_degrees()
+        };
+
+        let mut found = Vec::new();
+        for band in self.band_of(lat - radius)..=self.band_of(lat + radius) {
+            let (first, cells) = (self.band_starts[band], self.band_starts[band + 1] - self.band_starts[band]);
+            let start = ((lon - half_width) / 360.0 * cells as f64).floor() as i64;
+            let end = ((lon + half_width) / 360.0 * cells as f64).floor() as i64;
+            let span = ((end - start + 1) as usize).min(cells);
+            for offset in 0..span {
+                let cell = first + (start + offset as i64).rem_euclid(cells as i64) as usize;
+                for &star in &self.entries[self.cell_starts[cell]..self.cell_starts[cell + 1]] {
+                    if self.directions[star].dot(&center) >= cos_radius {
+                        found.push(star);
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Stars that land on the image of a camera with `attitude`, widened by `margin` pixels.
+    /// Both search the field radius of the projection, so these are the same stars, in index
+    /// order, that `frustum_stars` returns.
+    pub fn frustum(&self, attitude: &Attitude, projection: &dyn Projection, margin: f64) -> Result<Vec<&Star>, SatSightError> {
+        let boresight = attitude.rotate(&Vector3::z());
+        let field_radius = projection.field_radius(margin);
+        let candidates = self.cone_indices(&boresight, field_radius)?;
+        Ok(candidates
+            .into_iter()
+            .filter(|&star| in_frustum(projection, &attitude.inverse_rotate(&self.directions[star]), field_radius, margin))
+            .map(|star| &self.stars[star])
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sat_sight::camera::{CameraModel, Distortion};
+    use crate::sat_sight::photometry::MagnitudeBand;
+    use crate::sat_sight::{frustum_stars, StarId};
+
+    /// Stars spread over the whole sphere from a fixed seed, denser than the BSC
+    fn random_stars(count: usize) -> Vec<Star> {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        (0..count)
+            .map(|hr| Star {
+                hr: hr as u32,
+                id: StarId::from_hr(hr as u32),
+                lat: (2.0 * next() - 1.0).asin().to_degrees(),
+                lon: 360.0 * next(),
+                mag: 5.0,
+                band: MagnitudeBand::V,
+                fingure_print: 0.0,
+                flags: 0,
+            })
+            .collect()
+    }
+
+    fn sorted_hrs<'a>(stars: impl IntoIterator<Item = &'a Star>) -> Vec<u32> {
+        let mut hrs: Vec<u32> = stars.into_iter().map(|star| star.hr).collect();
+        hrs.sort_unstable();
+        hrs
+    }
+
+    fn attitudes(count: usize) -> Vec<Attitude> {
+        (0..count)
+            .map(|i| {
+                let i = i as f64;
+                Attitude::from_ra_dec_roll((i * 137.5) % 360.0, (i * 23.3) % 180.0 - 90.0, (i * 61.0) % 360.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cone_matches_a_full_scan() {
+        let stars = random_stars(20_000);
+        let queries: [(f64, f64, f64); 8] = [
+            (0.0, 0.0, 10.0),
+            (89.0, 10.0, 5.0),    // Reaches over the north pole
+            (-85.0, 300.0, 7.0),  // and the south pole
+            (-90.0, 0.0, 3.0),    // Centred on a pole
+            (30.0, 359.5, 20.0),  // Across longitude 0
+            (60.0, 180.0, 45.0),
+            (10.0, 20.0, 120.0),  // Wider than a hemisphere
+            (45.0, 90.0, 0.5),
+        ];
+        for cell_size in [0.7, 5.0, 13.0, 100.0] {
+            let index = SkyIndex::new(stars.clone(), cell_size).unwrap();
+            for (lat, lon, radius) in queries {
+                let center = lat_lon_to_unit_vector(lat, lon);
+                let cos_radius = radius.to_radians().cos();
+                let expected = sorted_hrs(
+                    stars.iter().filter(|star| lat_lon_to_unit_vector(star.lat, star.lon).dot(&center) >= cos_radius),
+                );
+                let found = sorted_hrs(index.cone(&center, radius).unwrap());
+                assert_eq!(found, expected, "cell size {} cone at ({}, {}) radius {}", cell_size, lat, lon, radius);
+            }
+        }
+    }
+
+    #[test]
+    fn cone_rejects_a_zero_center() {
+        let index = SkyIndex::new(random_stars(10), 10.0).unwrap();
+        assert!(index.cone(&Vector3::zeros(), 10.0).is_err());
+        assert!(SkyIndex::new(Vec::new(), 0.0).is_err());
+    }
+
+    #[test]
+    fn frustum_matches_frustum_stars() {
+        let stars = random_stars(4_000);
+        let index = SkyIndex::new(stars.clone(), 5.0).unwrap();
+
+        let pinhole = CameraModel::from_fov(1000, 700, 40.0, 0.005).unwrap();
+        let mut barrel = pinhole.clone();
+        barrel.distortion = Distortion { k1: -0.3, ..Distortion::default() };
+        let mut wavy = pinhole.clone();
+        wavy.distortion = Distortion { k1: -0.2, k2: 0.05, k3: -0.01, p1: 0.001, p2: -0.0005 };
+
+        for camera in [&pinhole, &barrel, &wavy] {
+            for attitude in attitudes(200) {
+                for margin in [0.0, 25.0] {
+                    let expected = sorted_hrs(&frustum_stars(stars.clone(), &attitude, camera, margin));
+                    let found = sorted_hrs(index.frustum(&attitude, camera, margin).unwrap());
+                    assert_eq!(found, expected, "distortion {:?} margin {}", camera.distortion, margin);
+                }
+            }
+        }
+    }
+}
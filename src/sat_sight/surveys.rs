@@ -0,0 +1,656 @@
+//! Importers for the Hipparcos, Tycho-2 and Gaia DR3 bulk catalogs.
+//!
+//! The BSC stops near magnitude 6.5 while the narrow field tracker sees to
+//! about 7.5, so denser catalogs are read from their standard bulk files on
+//! local disk. Every importer produces a `SurveyStar` carrying the identifiers
+//! of its source catalog; `cross_match` combines catalogs so a star keeps its
+//! HR, HD, HIP, TYC and Gaia identifiers together. `SurveyStar::to_star`
+//! gives the galactic J2000 `Star` used by the rest of `sat_sight`.
+
+use csv::{ReaderBuilder, StringRecord};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+use nalgebra::Vector3;
+
+use super::astrometry::EpochStar;
+use super::catalog::{bsc_field, find_column, parse_optional, quality_flags, require_column, BscStar};
+use super::error::SatSightError;
+use super::photometry::{MagnitudeBand, SensorModel};
+use super::{Star, StarId, FLAG_MISSING_PHOTOMETRY, FLAG_MULTIPLE, FLAG_VARIABLE};
+
+/// Catalog identifiers of a star, `None` when the star is not in that catalog
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StarIds {
+    pub hr: Option<u32>,             // Harvard Revised Number (BSC)
+    pub hd: Option<u32>,             // Henry Draper Catalog Number
+    pub hip: Option<u32>,            // Hipparcos Catalogue number
+    pub tyc: Option<(u16, u16, u8)>, // Tycho-2 TYC1-TYC2-TYC3
+    pub gaia: Option<u64>,           // Gaia DR3 source_id
+}
+
+impl StarIds {
+    /// True when both records carry the same number in any catalog
+    pub fn shares_id(&self, other: &StarIds) -> bool {
+        fn same<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+            matches!((a, b), (Some(a), Some(b)) if a == b)
+        }
+        same(self.hr, other.hr)
+            || same(self.hd, other.hd)
+            || same(self.hip, other.hip)
+            || same(self.tyc, other.tyc)
+            || same(self.gaia, other.gaia)
+    }
+
+    /// The identifier a star is known by, in order of preference HR, HIP, TYC, Gaia and HD
+    pub fn primary(&self) -> StarId {
+        self.hr
+            .map(StarId::Hr)
+            .or(self.hip.map(StarId::Hip))
+            .or(self.tyc.map(|(tyc1, tyc2, tyc3)| StarId::Tyc(tyc1, tyc2, tyc3)))
+            .or(self.gaia.map(StarId::Gaia))
+            .or(self.hd.map(StarId::Hd))
+            .unwrap_or_default()
+    }
+
+    /// Fills in the identifiers this record is missing from another record of the same star
+    pub fn fill_from(&mut self, other: &StarIds) {
+        self.hr = self.hr.or(other.hr);
+        self.hd = self.hd.or(other.hd);
+        self.hip = self.hip.or(other.hip);
+        self.tyc = self.tyc.or(other.tyc);
+        self.gaia = self.gaia.or(other.gaia);
+    }
+}
+
+/// A star from one of the survey catalogs, or several of them after `cross_match`.
+///
+/// Positions are ICRS at `epoch` with proper motions in arcseconds per year, the same
+/// units as `BscStar` and `EpochStar`.
+#[derive(Clone, Debug)]
+pub struct SurveyStar {
+    pub ids: StarIds,
+    pub ra: f64,               // Right ascension at `epoch` (deg)
+    pub dec: f64,              // Declination at `epoch` (deg)
+    pub pm_ra: f64,            // Proper motion in RA, cos(dec) * d(ra)/dt (arcsec/yr)
+    pub pm_dec: f64,           // Proper motion in Dec (arcsec/yr)
+    pub parallax: Option<f32>, // Parallax (arcsec)
+    pub vmag: f32,             // Johnson V, converted from the survey's own band
+    pub b_v: Option<f32>,      // Johnson B-V
+    pub sp_type: String,       // Spectral type, empty when unknown
+    pub epoch: f64,            // Julian epoch of the position
+    pub flags: u32,            // Catalog quality flags, see `Star::flags`
+}
+
+impl From<&BscStar> for SurveyStar {
+    fn from(star: &BscStar) -> Self {
+        SurveyStar {
+            ids: StarIds {
+                hr: Some(star.hr),
+                hd: star.hd,
+                ..StarIds::default()
+            },
+            ra: star.ra,
+            dec: star.dec,
+            pm_ra: star.pm_ra,
+            pm_dec: star.pm_dec,
+            parallax: star.parallax,
+            vmag: star.vmag,
+            b_v: star.b_v,
+            sp_type: star.sp_type.clone(),
+            epoch: 2000.0,
+            flags: star.flags(),
+        }
+    }
+}
+
+impl SurveyStar {
+    /// The star as an `EpochStar` at its catalog epoch, identified by `StarIds::primary`
+    pub fn to_epoch_star(&self) -> EpochStar {
+        EpochStar {
+            id: self.ids.primary(),
+            ra: self.ra,
+            dec: self.dec,
+            pm_ra: self.pm_ra,
+            pm_dec: self.pm_dec,
+            mag: self.vmag,
+            epoch: self.epoch,
+            flags: self.flags,
+        }
+    }
+
+    /// Propagates the star to J2000 and converts it into the galactic `Star` used by the
+    /// rest of `sat_sight`
+    pub fn to_star(&self) -> Star {
+        self.to_epoch_star().propagate(2000.0).to_star()
+    }
+
+    /// Same as `to_star` with the magnitude converted into the sensor's band
+    pub fn to_sensor_star(&self, model: &SensorModel) -> Star {
+        Star {
+            mag: model.sensor_magnitude(self.vmag, self.b_v, &self.sp_type),
+            band: MagnitudeBand::Sensor,
+            ..self.to_star()
+        }
+    }
+
+    /// Unit vector of the J2000 position
+    fn j2000_vector(&self) -> Vector3<f64> {
+        self.to_epoch_star().propagate(2000.0).unit_vector()
+    }
+}
+
+// ==== Hipparcos ==================================================================================
+// hip_main.dat of the Hipparcos Catalogue (ESA 1997, I/239), fields H0 to H77 separated by '|'
+// =================================================================================================
+
+/// Returns the trimmed Hipparcos field `H<index>` of a record
+fn hipparcos_field<'a>(fields: &[&'a str], index: usize) -> &'a str {
+    fields.get(index).map_or("", |field| field.trim())
+}
+
+/// Parses an optional numeric Hipparcos field, blank fields are `None`
+fn parse_hipparcos_field<T: FromStr>(fields: &[&str], index: usize, line_number: u64) -> Result<Option<T>, SatSightError> {
+    let text = hipparcos_field(fields, index);
+    if text.is_empty() {
+        return Ok(None);
+    }
+    text.parse()
+        .map(Some)
+        .map_err(|_| SatSightError::parse(line_number, &format!("H{}", index), format!("invalid value '{}'", text)))
+}
+
+/// Parses one `hip_main.dat` record.
+/// Returns `None` for the entries without an astrometric solution, those have no position in degrees.
+pub fn parse_hipparcos_record(line: &str, line_number: u64) -> Result<Option<SurveyStar>, SatSightError> {
+    let fields: Vec<&str> = line.split('|').collect();
+    let hip = parse_hipparcos_field(&fields, 1, line_number)?
+        .ok_or_else(|| SatSightError::parse(line_number, "H1", "missing HIP number"))?;
+
+    let (ra, dec) = match (parse_hipparcos_field(&fields, 8, line_number)?, parse_hipparcos_field(&fields, 9, line_number)?) {
+        (Some(ra), Some(dec)) => (ra, dec),
+        _ => return Ok(None),
+    };
+    let vmag = match parse_hipparcos_field(&fields, 5, line_number)? {
+        Some(vmag) => vmag,
+        None => return Ok(None),
+    };
+
+    let pm_ra = parse_hipparcos_field::<f64>(&fields, 12, line_number)?.unwrap_or(0.0) / 1000.0;
+    let pm_dec = parse_hipparcos_field::<f64>(&fields, 13, line_number)?.unwrap_or(0.0) / 1000.0;
+    let b_v = parse_hipparcos_field(&fields, 37, line_number)?;
+    let flags = quality_flags(
+        !hipparcos_field(&fields, 6).is_empty(),
+        !hipparcos_field(&fields, 55).is_empty() || !hipparcos_field(&fields, 59).is_empty(),
+        Some((pm_ra, pm_dec)),
+        b_v.is_none(),
+    );
+
+    Ok(Some(SurveyStar {
+        ids: StarIds {
+            hd: parse_hipparcos_field(&fields, 71, line_number)?,
+            hip: Some(hip),
+            ..StarIds::default()
+        },
+        ra,
+        dec,
+        pm_ra,
+        pm_dec,
+        parallax: parse_hipparcos_field::<f32>(&fields, 11, line_number)?.map(|plx| plx / 1000.0),
+        vmag,
+        b_v,
+        sp_type: hipparcos_field(&fields, 76).to_string(),
+        epoch: 1991.25,
+        flags,
+    }))
+}
+
+/// Parses `hip_main.dat` and returns the stars brighter than `limiting_mag`
+pub fn parse_hipparcos_file(file: File, limiting_mag: f32) -> Result<Vec<SurveyStar>, SatSightError> {
+    let reader = BufReader::new(file);
+
+    let mut stars: Vec<SurveyStar> = Vec::new();
+    for (line_number, line) in (1..).zip(reader.lines()) {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(star) = parse_hipparcos_record(&line, line_number)? {
+            if star.vmag <= limiting_mag {
+                stars.push(star);
+            }
+        }
+    }
+    Ok(stars)
+}
+
+// ==== Tycho-2 ====================================================================================
+// tyc2.dat of the Tycho-2 Catalogue (Hog et al. 2000, I/259), bytes are 1 based and inclusive
+// =================================================================================================
+
+/// Johnson V and B-V from the Tycho BT and VT magnitudes (ESA 1997, vol 1 sec 1.3)
+pub fn tycho_to_johnson(bt: f32, vt: f32) -> (f32, f32) {
+    let bt_vt = bt - vt;
+    (vt - 0.090 * bt_vt, 0.850 * bt_vt)
+}
+
+/// Parses one `tyc2.dat` record.
+///
+/// Stars with a mean position use it (ICRS at J2000). Stars flagged `X` have none and use
+/// their observed position at its own epoch without proper motion. Returns `None` for
+/// stars with neither a BT nor a VT magnitude.
+pub fn parse_tycho2_record(line: &str, line_number: u64) -> Result<Option<SurveyStar>, SatSightError> {
+    let tyc1 = parse_optional(line, 1, 4, "TYC1", line_number)?;
+    let tyc2 = parse_optional(line, 6, 10, "TYC2", line_number)?;
+    let tyc3 = parse_optional(line, 12, 12, "TYC3", line_number)?;
+    let tyc = match (tyc1, tyc2, tyc3) {
+        (Some(tyc1), Some(tyc2), Some(tyc3)) => (tyc1, tyc2, tyc3),
+        _ => return Err(SatSightError::parse(line_number, "TYC", "missing Tycho identifier")),
+    };
+
+    let bt: Option<f32> = parse_optional(line, 111, 116, "BTmag", line_number)?;
+    let vt: Option<f32> = parse_optional(line, 124, 129, "VTmag", line_number)?;
+    let (vmag, b_v) = match (bt, vt) {
+        (Some(bt), Some(vt)) => {
+            let (vmag, b_v) = tycho_to_johnson(bt, vt);
+            (vmag, Some(b_v))
+        }
+        (None, Some(vt)) => (vt, None),
+        (Some(bt), None) => (bt, None),
+        (None, None) => return Ok(None),
+    };
+
+    let pflag = bsc_field(line, 14, 14);
+    let (ra, dec, pm_ra, pm_dec, epoch) = if pflag == "X" {
+        let ra = parse_optional(line, 153, 164, "RAdeg", line_number)?;
+        let dec = parse_optional(line, 166, 177, "DEdeg", line_number)?;
+        let ep_ra: f64 = parse_optional(line, 179, 182, "EpRA", line_number)?.unwrap_or(0.0);
+        let ep_dec: f64 = parse_optional(line, 184, 187, "EpDE", line_number)?.unwrap_or(0.0);
+        match (ra, dec) {
+            (Some(ra), Some(dec)) => (ra, dec, 0.0, 0.0, 1990.0 + (ep_ra + ep_dec) / 2.0),
+            _ => return Err(SatSightError::parse(line_number, "RAdeg", "missing observed position")),
+        }
+    } else {
+        let ra = parse_optional(line, 16, 27, "RAmdeg", line_number)?;
+        let dec = parse_optional(line, 29, 40, "DEmdeg", line_number)?;
+        let pm_ra: f64 = parse_optional(line, 42, 48, "pmRA", line_number)?.unwrap_or(0.0);
+        let pm_dec: f64 = parse_optional(line, 50, 56, "pmDE", line_number)?.unwrap_or(0.0);
+        match (ra, dec) {
+            (Some(ra), Some(dec)) => (ra, dec, pm_ra / 1000.0, pm_dec / 1000.0, 2000.0),
+            _ => return Err(SatSightError::parse(line_number, "RAmdeg", "missing mean position")),
+        }
+    };
+
+    // 'P' marks the photocentre of two Tycho entries, the CCDM component marks known multiples
+    let multiple = pflag == "P" || !bsc_field(line, 149, 151).is_empty();
+
+    Ok(Some(SurveyStar {
+        ids: StarIds {
+            hip: parse_optional(line, 143, 148, "HIP", line_number)?,
+            tyc: Some(tyc),
+            ..StarIds::default()
+        },
+        ra,
+        dec,
+        pm_ra,
+        pm_dec,
+        parallax: None,
+        vmag,
+        b_v,
+        sp_type: String::new(),
+        epoch,
+        flags: quality_flags(false, multiple, Some((pm_ra, pm_dec)), b_v.is_none()),
+    }))
+}
+
+/// Parses `tyc2.dat` (or one of its `tyc2.dat.NN` parts) and returns the stars brighter
+/// than `limiting_mag`
+pub fn parse_tycho2_file(file: File, limiting_mag: f32) -> Result<Vec<SurveyStar>, SatSightError> {
+    let reader = BufReader::new(file);
+
+    let mut stars: Vec<SurveyStar> = Vec::new();
+    for (line_number, line) in (1..).zip(reader.lines()) {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(star) = parse_tycho2_record(&line, line_number)? {
+            if star.vmag <= limiting_mag {
+                stars.push(star);
+            }
+        }
+    }
+    Ok(stars)
+}
+
+// ==== Gaia DR3 ===================================================================================
+// GaiaSource_*.csv bulk files (ECSV with '#' metadata lines and "null" for missing values),
+// decompressed beforehand
+// =================================================================================================
+
+/// Johnson V from the Gaia G magnitude and BP-RP colour (Riello et al. 2021, table C.2).
+/// The fit holds for -0.5 < BP-RP < 2.75.
+pub fn gaia_to_johnson_v(g: f32, bp_rp: f32) -> f32 {
+    let x = bp_rp as f64;
+    let g_v = -0.02704 + 0.01424 * x - 0.2156 * x * x + 0.01426 * x * x * x;
+    (g as f64 - g_v) as f32
+}
+
+/// Parses a Gaia csv field, blank and "null" fields are `None`
+fn parse_gaia_field<T: FromStr>(record: &StringRecord, column: usize, name: &str) -> Result<Option<T>, SatSightError> {
+    let text = record.get(column).unwrap_or("").trim();
+    if text.is_empty() || text == "null" {
+        return Ok(None);
+    }
+    let line = record.position().map_or(0, |position| position.line());
+    text.parse()
+        .map(Some)
+        .map_err(|_| SatSightError::parse(line, name, format!("invalid value '{}'", text)))
+}
+
+/// Parses a Gaia DR3 `gaia_source` csv file and returns the stars brighter than `limiting_mag` in V.
+///
+/// Positions are at the DR3 reference epoch 2016.0. Stars without BP-RP use G as V and are
+/// flagged as missing photometry; Gaia has no B-V so `b_v` is always `None`.
+pub fn parse_gaia_dr3_file(file: File, limiting_mag: f32) -> Result<Vec<SurveyStar>, SatSightError> {
+    parse_gaia_dr3(file, limiting_mag)
+}
+
+fn parse_gaia_dr3<R: Read>(input: R, limiting_mag: f32) -> Result<Vec<SurveyStar>, SatSightError> {
+    let mut reader = ReaderBuilder::new().comment(Some(b'#')).from_reader(input);
+    let headers = reader.headers()?.clone();
+
+    let source_id_column = require_column(&headers, "source_id")?;
+    let ra_column = require_column(&headers, "ra")?;
+    let dec_column = require_column(&headers, "dec")?;
+    let g_column = require_column(&headers, "phot_g_mean_mag")?;
+    let pm_ra_column = find_column(&headers, "pmra");
+    let pm_dec_column = find_column(&headers, "pmdec");
+    let parallax_column = find_column(&headers, "parallax");
+    let epoch_column = find_column(&headers, "ref_epoch");
+    let bp_rp_column = find_column(&headers, "bp_rp");
+    let variable_column = find_column(&headers, "phot_variable_flag");
+    let non_single_column = find_column(&headers, "non_single_star");
+
+    let optional = |record: &StringRecord, column: Option<usize>, name: &str| -> Result<Option<f64>, SatSightError> {
+        match column {
+            Some(column) => parse_gaia_field(record, column, name),
+            None => Ok(None),
+        }
+    };
+
+    let mut stars: Vec<SurveyStar> = Vec::new();
+    for result in reader.records() {
+        let record = result?;
+
+        let g: f32 = match parse_gaia_field(&record, g_column, "phot_g_mean_mag")? {
+            Some(g) => g,
+            None => continue,
+        };
+        let bp_rp = optional(&record, bp_rp_column, "bp_rp")?.map(|bp_rp| bp_rp as f32);
+        let vmag = bp_rp.map_or(g, |bp_rp| gaia_to_johnson_v(g, bp_rp));
+        if vmag > limiting_mag {
+            continue;
+        }
+
+        let line = record.position().map_or(0, |position| position.line());
+        let source_id = parse_gaia_field(&record, source_id_column, "source_id")?
+            .ok_or_else(|| SatSightError::parse(line, "source_id", "missing value"))?;
+        let (ra, dec) = match (parse_gaia_field(&record, ra_column, "ra")?, parse_gaia_field(&record, dec_column, "dec")?) {
+            (Some(ra), Some(dec)) => (ra, dec),
+            _ => continue,
+        };
+        let pm_ra = optional(&record, pm_ra_column, "pmra")?.unwrap_or(0.0) / 1000.0;
+        let pm_dec = optional(&record, pm_dec_column, "pmdec")?.unwrap_or(0.0) / 1000.0;
+
+        let variable = variable_column
+            .and_then(|column| record.get(column))
+            .is_some_and(|flag| flag.trim() == "VARIABLE");
+        let multiple = optional(&record, non_single_column, "non_single_star")?.is_some_and(|value| value > 0.0);
+
+        stars.push(SurveyStar {
+            ids: StarIds {
+                gaia: Some(source_id),
+                ..StarIds::default()
+            },
+            ra,
+            dec,
+            pm_ra,
+            pm_dec,
+            parallax: optional(&record, parallax_column, "parallax")?.map(|plx| (plx / 1000.0) as f32),
+            vmag,
+            b_v: None,
+            sp_type: String::new(),
+            epoch: optional(&record, epoch_column, "ref_epoch")?.unwrap_or(2016.0),
+            flags: quality_flags(variable, multiple, Some((pm_ra, pm_dec)), bp_rp.is_none()),
+        });
+    }
+    Ok(stars)
+}
+
+// ==== Cross Matching =============================================================================
+// Combining catalogs while keeping every identifier of a star
+// =================================================================================================
+
+/// Lookup keys of every identifier a star has, tagged by catalog
+fn id_keys(ids: &StarIds) -> impl Iterator<Item = (u8, u64)> {
+    [
+        ids.hip.map(|hip| (0, hip as u64)),
+        ids.hd.map(|hd| (1, hd as u64)),
+        ids.hr.map(|hr| (2, hr as u64)),
+        ids.tyc.map(|(tyc1, tyc2, tyc3)| (3, ((tyc1 as u64) << 24) | ((tyc2 as u64) << 8) | tyc3 as u64)),
+        ids.gaia.map(|gaia| (4, gaia)),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Cross-matches two catalogs and returns their union.
+///
+/// Every `base` star is matched to an `other` star first by a shared identifier (HIP, HD, HR,
+/// TYC or Gaia), then by the nearest J2000 position within `radius_arcsec`. Each `other` star
+/// is used at most once. Matched `base` stars keep their position and magnitude and take the
+/// identifiers, colour, spectral type and variable/multiple flags they are missing from
+/// their match. The `other` stars without a match are appended after the `base` stars.
+///
+/// Matching the BSC against Hipparcos links HR to HIP through the HD numbers, Tycho-2 links
+/// through HIP and Gaia, which carries no other identifiers, by position.
+pub fn cross_match(base: &[SurveyStar], other: &[SurveyStar], radius_arcsec: f64) -> Vec<SurveyStar> {
+    // Only the first star with a given number is used, duplicates fall back to position
+    let mut by_id: HashMap<(u8, u64), usize> = HashMap::new();
+    for (index, star) in other.iter().enumerate() {
+        for key in id_keys(&star.ids) {
+            by_id.entry(key).or_insert(index);
+        }
+    }
+
+    let mut matches: Vec<Option<usize>> = vec![None; base.len()];
+    let mut used = vec![false; other.len()];
+    for (index, star) in base.iter().enumerate() {
+        if let Some(other_index) = id_keys(&star.ids).find_map(|key| by_id.get(&key).copied()) {
+            if !used[other_index] {
+                matches[index] = Some(other_index);
+                used[other_index] = true;
+            }
+        }
+    }
+
+    // Position match the rest with a declination sweep over the unused `other` stars
+    let radius = (radius_arcsec / 3600.0).max(0.0);
+    let cos_radius = radius.to_radians().cos();
+    let mut candidates: Vec<(f64, Vector3<f64>, usize)> = other
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !used[*index])
+        .map(|(index, star)| {
+            let direction = star.j2000_vector();
+            (direction.z.clamp(-1.0, 1.0).asin().to_degrees(), direction, index)
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    for (index, star) in base.iter().enumerate() {
+        if matches[index].is_some() {
+            continue;
+        }
+        let direction = star.j2000_vector();
+        let dec = direction.z.clamp(-1.0, 1.0).asin().to_degrees();
+        let start = candidates.partition_point(|candidate| candidate.0 < dec - radius);
+
+        let mut best: Option<(f64, usize)> = None;
+        for (candidate_dec, candidate, other_index) in &candidates[start..] {
+            if *candidate_dec > dec + radius {
+                break;
+            }
+            let cos_angle = direction.dot(candidate);
+            if !used[*other_index] && cos_angle >= cos_radius && best.is_none_or(|(best_cos, _)| cos_angle > best_cos) {
+                best = Some((cos_angle, *other_index));
+            }
+        }
+        if let Some((_, other_index)) = best {
+            matches[index] = Some(other_index);
+            used[other_index] = true;
+        }
+    }
+
+    let mut merged: Vec<SurveyStar> = base
+        .iter()
+        .zip(&matches)
+        .map(|(star, matched)| {
+            let mut star = star.clone();
+            if let Some(other_index) = matched {
+                let other = &other[*other_index];
+                star.ids.fill_from(&other.ids);
+                if star.b_v.is_none() && other.b_v.is_some() {
+                    star.b_v = other.b_v;
+                    star.flags &= !FLAG_MISSING_PHOTOMETRY;
+                }
+                if star.sp_type.is_empty() {
+                    star.sp_type = other.sp_type.clone();
+                }
+                star.parallax = star.parallax.or(other.parallax);
+                star.flags |= other.flags & (FLAG_VARIABLE | FLAG_MULTIPLE);
+            }
+            star
+        })
+        .collect();
+    merged.extend(other.iter().zip(&used).filter(|(_, used)| !**used).map(|(star, _)| star.clone()));
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn survey_star(ids: StarIds) -> SurveyStar {
+        SurveyStar {
+            ids,
+            ra: 10.0,
+            dec: 20.0,
+            pm_ra: 0.0,
+            pm_dec: 0.0,
+            parallax: None,
+            vmag: 7.0,
+            b_v: None,
+            sp_type: String::new(),
+            epoch: 2000.0,
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn stars_outside_the_bsc_keep_their_own_id() {
+        let hip = survey_star(StarIds { hip: Some(12), hd: Some(34), ..StarIds::default() }).to_star();
+        let tyc = survey_star(StarIds { tyc: Some((1, 2, 1)), ..StarIds::default() }).to_star();
+        let gaia = survey_star(StarIds { gaia: Some(5_000_000_001), ..StarIds::default() }).to_star();
+        let hr = survey_star(StarIds { hr: Some(7), hip: Some(99), ..StarIds::default() }).to_star();
+
+        assert_eq!((hip.hr, hip.id), (0, StarId::Hip(12)));
+        assert_eq!((tyc.hr, tyc.id), (0, StarId::Tyc(1, 2, 1)));
+        assert_eq!((gaia.hr, gaia.id), (0, StarId::Gaia(5_000_000_001)));
+        assert_eq!((hr.hr, hr.id), (7, StarId::Hr(7)));
+        assert_eq!(tyc.id.to_string(), "TYC 1-2-1");
+        assert_eq!(survey_star(StarIds::default()).to_star().id, StarId::Unknown);
+    }
+
+    // HIP 1 from hip_main.dat
+    const HIPPARCOS_RECORD: &str = "H|           1| |00 00 00.22|+01 05 20.4| 9.10| |H|000.00091185|+01.08901332| |   3.54|   -5.20|   -1.88|  1.32|  0.74|  1.39|  1.36|  0.81| 0.32|-0.07|-0.11|-0.24| 0.09|-0.01| 0.10|-0.01| 0.01| 0.34|  0| 0.74|     1| 9.643|0.020| 9.130|0.019| |0.482|0.025|T|0.55|0.03|L| | 9.2043|0.0020|0.017| 87| | 9.17| 9.24|       | | | |          | |  |  | | | |  |   |       |     |     |    | | | |  224700|B+00 5077 |          |          |0.66|F5          |S ";
+
+    // TYC 1-8-1 from tyc2.dat
+    const TYCHO2_RECORD: &str = "0001 00008 1| |  2.31750494|  2.23184345|  -16.3|   -9.0| 68| 73| 1.7| 1.8|1958.89|1951.94| 4|1.0|1.0|0.9|1.0|12.146|0.158|12.146|0.223|999| |         |  2.31754222|  2.23186444|1.67|1.54| 88.0|100.8| |-0.2";
+
+    // Proxima Centauri from a gaia_source file, and a star too faint for the tests' limit
+    const GAIA_DR3_FILE: &str = "# %ECSV 1.0\n\
+source_id,ra,dec,parallax,pmra,pmdec,ref_epoch,phot_g_mean_mag,bp_rp,phot_variable_flag,non_single_star\n\
+5853498713190525696,217.39232147200883,-62.67607511676666,768.0665391873573,-3781.741008265163,769.4650146478623,2016.0,8.984749,3.8043,VARIABLE,0\n\
+5853498713190525697,217.4,-62.7,null,null,null,2016.0,15.2,null,NOT_AVAILABLE,0\n";
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance
+    }
+
+    #[test]
+    fn hipparcos_record_fields() {
+        let star = parse_hipparcos_record(HIPPARCOS_RECORD, 1).unwrap().unwrap();
+        assert_eq!(star.ids, StarIds { hip: Some(1), hd: Some(224700), ..StarIds::default() });
+        assert!(close(star.ra, 0.00091185, 1e-12) && close(star.dec, 1.08901332, 1e-12));
+        assert_eq!((star.vmag, star.b_v, star.parallax), (9.10, Some(0.482), Some(0.00354)));
+        assert!(close(star.pm_ra, -0.00520, 1e-12) && close(star.pm_dec, -0.00188, 1e-12));
+        assert_eq!((star.sp_type.as_str(), star.epoch, star.flags), ("F5", 1991.25, 0));
+        assert_eq!(star.to_star().id, StarId::Hip(1));
+    }
+
+    #[test]
+    fn tycho2_record_fields() {
+        let star = parse_tycho2_record(TYCHO2_RECORD, 1).unwrap().unwrap();
+        assert_eq!(star.ids, StarIds { tyc: Some((1, 8, 1)), ..StarIds::default() });
+        assert!(close(star.ra, 2.31750494, 1e-12) && close(star.dec, 2.23184345, 1e-12));
+        // BT = VT, so V = VT and B-V = 0
+        assert_eq!((star.vmag, star.b_v, star.epoch), (12.146, Some(0.0), 2000.0));
+        assert!(close(star.pm_ra, -0.0163, 1e-12) && close(star.pm_dec, -0.0090, 1e-12));
+        assert_eq!(star.to_star().id, StarId::Tyc(1, 8, 1));
+    }
+
+    #[test]
+    fn gaia_dr3_file_fields() {
+        let stars = parse_gaia_dr3(GAIA_DR3_FILE.as_bytes(), 12.0).unwrap();
+        assert_eq!(stars.len(), 1);
+        let star = &stars[0];
+        assert_eq!(star.ids, StarIds { gaia: Some(5_853_498_713_190_525_696), ..StarIds::default() });
+        assert!(close(star.ra, 217.39232147200883, 1e-12) && close(star.dec, -62.67607511676666, 1e-12));
+        assert_eq!((star.vmag, star.b_v, star.epoch), (gaia_to_johnson_v(8.984749, 3.8043), None, 2016.0));
+        assert!(close(star.pm_ra, -3.781741008265163, 1e-12) && close(star.pm_dec, 0.7694650146478623, 1e-12));
+        assert_eq!(star.flags & (FLAG_VARIABLE | FLAG_MULTIPLE), FLAG_VARIABLE);
+        assert_eq!(star.to_star().id, StarId::Gaia(5_853_498_713_190_525_696));
+
+        assert_eq!(parse_gaia_dr3(GAIA_DR3_FILE.as_bytes(), 16.0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cross_match_by_id_keeps_every_identifier() {
+        // The same star a degree apart, matched only through the shared HD number
+        let bsc = SurveyStar { ra: 1.0, ..survey_star(StarIds { hr: Some(9), hd: Some(224700), ..StarIds::default() }) };
+        let hipparcos = parse_hipparcos_record(HIPPARCOS_RECORD, 1).unwrap().unwrap();
+
+        let merged = cross_match(&[bsc], &[hipparcos], 1.0);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].ids, StarIds { hr: Some(9), hd: Some(224700), hip: Some(1), ..StarIds::default() });
+        assert_eq!((merged[0].ra, merged[0].b_v, merged[0].sp_type.as_str()), (1.0, Some(0.482), "F5"));
+        assert_eq!(merged[0].to_star().id, StarId::Hr(9));
+    }
+
+    #[test]
+    fn cross_match_by_position() {
+        let bsc = survey_star(StarIds { hr: Some(5), hd: Some(7), ..StarIds::default() });
+        let near = SurveyStar { dec: 20.0 + 0.5 / 3600.0, ..survey_star(StarIds { hip: Some(99), tyc: Some((2, 3, 1)), ..StarIds::default() }) };
+        let far = SurveyStar { dec: 20.0 + 5.0 / 3600.0, ..survey_star(StarIds { tyc: Some((2, 4, 1)), ..StarIds::default() }) };
+
+        let merged = cross_match(&[bsc], &[far.clone(), near], 2.0);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].ids, StarIds { hr: Some(5), hd: Some(7), hip: Some(99), tyc: Some((2, 3, 1)), gaia: None });
+        assert_eq!(merged[0].dec, 20.0);
+        assert_eq!(merged[1].ids, far.ids);
+    }
+}